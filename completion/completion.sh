@@ -17,17 +17,23 @@ _system76-power ()
     prev="${COMP_WORDS[COMP_CWORD-1]}"
 
     # 1st level options
-    opts="charge-threshold daemon graphics help profile --version --help"
+    opts="backlight charge-profile charge-threshold cpu daemon graphics help info keyboard-backlight monitor profile reload smt temps --version --help"
 
     # 2nd/3rd level options
     case "${prev}" in
         graphics)
-            local _opts="compute integrated hybrid nvidia power switchable --help"
+            local _opts="compute functions integrated integrated-pm hybrid list nvidia power status switchable --help"
             COMPREPLY=( $(compgen -W "${_opts}" -- ${cur}) )
             return 0
             ;;
 
-        battery|balanced|compute|integrated|hybrid|nvidia|performance|switchable|on|off|auto)
+        compute|integrated|integrated-pm|hybrid|nvidia)
+            local _opts="--dry-run --force --help"
+            COMPREPLY=( $(compgen -W "${_opts}" -- ${cur}) )
+            return 0
+            ;;
+
+        battery|balanced|performance|switchable|on|off|auto)
             local _opts="--help"
             COMPREPLY=( $(compgen -W "${_opts}" -- ${cur}) )
             return 0
@@ -39,7 +45,25 @@ _system76-power ()
             ;;
 
 	     daemon)
-	          local _opts="--quiet --verbose --help"
+	          local _opts="--log-level --quiet --verbose --watch --help"
+            COMPREPLY=( $(compgen -W "${_opts}" -- ${cur}) )
+            return 0
+            ;;
+
+        smt|turbo)
+            local _opts="on off --help"
+            COMPREPLY=( $(compgen -W "${_opts}" -- ${cur}) )
+            return 0
+            ;;
+
+        backlight|keyboard-backlight)
+            local _opts="up down --help"
+            COMPREPLY=( $(compgen -W "${_opts}" -- ${cur}) )
+            return 0
+            ;;
+
+        cpu)
+            local _opts="governor governors max min turbo --help"
             COMPREPLY=( $(compgen -W "${_opts}" -- ${cur}) )
             return 0
             ;;
@@ -51,7 +75,7 @@ _system76-power ()
             ;;
 
 	      power)
-	          local _opts="auto on off --help"
+	          local _opts="auto on off --device --force --help"
             COMPREPLY=( $(compgen -W "${_opts}" -- ${cur}) )
             return 0
             ;;
@@ -60,7 +84,7 @@ _system76-power ()
             COMPREPLY=( $(compgen -W "${_opts}" -- ${cur}) )
             return 0
             ;;
-        --profile)
+        charge-profile|--profile)
             local _opts="full_charge balanced max_lifespan --help"
             COMPREPLY=( $(compgen -W "${_opts}" -- ${cur}) )
             return 0
@@ -0,0 +1,197 @@
+use std::{
+    cmp, io,
+    sync::atomic::{AtomicU8, Ordering},
+    thread,
+    time::Duration,
+};
+use sysfs_class::{Backlight, Brightness, Leds, SysClass};
+
+/// The interval between intermediate brightness values while fading.
+const FADE_INTERVAL_MS: u64 = 16;
+
+/// The default lowest brightness, as a percentage of the maximum brightness.
+pub const MIN_BRIGHTNESS_PERCENT: u8 = 5;
+
+// The floor used by `BacklightExt`, which may be changed by the daemon configuration.
+static MIN_BRIGHTNESS: AtomicU8 = AtomicU8::new(MIN_BRIGHTNESS_PERCENT);
+
+/// Changes the lowest brightness permitted by `BacklightExt`, as a percentage of the maximum.
+pub fn set_min_brightness_percent(percent: u8) {
+    MIN_BRIGHTNESS.store(cmp::min(percent, 100), Ordering::SeqCst);
+}
+
+/// The lowest brightness permitted for a backlight with the given maximum brightness, which is
+/// never less than 1 raw unit.
+fn min_brightness(max: u64) -> u64 {
+    cmp::max(max * u64::from(MIN_BRIGHTNESS.load(Ordering::SeqCst)) / 100, 1)
+}
+
+/// The number of steps between off and the maximum keyboard backlight brightness.
+const KEYBOARD_STEPS: u64 = 5;
+
+/// The zones of keyboards with a multi-zone RGB backlight, each with a `color_{zone}` file.
+pub const COLOR_ZONES: [&str; 4] = ["left", "center", "right", "extra"];
+
+/// Runs a fade on a worker thread, so that the caller is not held up for its duration. Errors
+/// are logged, as the caller has moved on by then.
+pub fn fade_in_background<B, F>(device: B, fade: F)
+where
+    B: SysClass + Send + 'static,
+    F: FnOnce(&B) -> io::Result<()> + Send + 'static,
+{
+    thread::spawn(move || {
+        if let Err(why) = fade(&device) {
+            log::warn!("failed to fade the brightness of {}: {}", device.id(), why);
+        }
+    });
+}
+
+/// Writes each step of a fade from `start` to `target`, every 16 ms. The fade stops early once
+/// the brightness is changed by something else, such as a later fade, which then takes over.
+fn fade<B: Brightness>(device: &B, start: u64, target: u64, duration_ms: u64) -> io::Result<()> {
+    let steps = duration_ms / FADE_INTERVAL_MS;
+    for step in 1..steps {
+        let delta = (target as i64 - start as i64) * step as i64 / steps as i64;
+        let value = (start as i64 + delta) as u64;
+        device.set_brightness(value)?;
+        thread::sleep(Duration::from_millis(FADE_INTERVAL_MS));
+        if device.brightness()? != value {
+            return Ok(());
+        }
+    }
+
+    device.set_brightness(target)
+}
+
+/// The first backlight reported by the kernel, which is the display panel on laptops.
+pub fn primary() -> io::Result<Backlight> {
+    Backlight::iter()
+        .next()
+        .unwrap_or_else(|| Err(io::Error::new(io::ErrorKind::NotFound, "no backlight found")))
+}
+
+/// Brightness changes which never black out the display. The brightness is kept at or above
+/// the configured minimum, which defaults to 5% of the maximum brightness or 1 raw unit. Use
+/// `Brightness::set_brightness` to write a raw value, including 0.
+pub trait BacklightExt {
+    /// Fades from the actual brightness to `target` over `duration_ms`, writing an intermediate
+    /// value every 16 ms, until the brightness is changed by something else. The target is
+    /// clamped with `clamp_brightness`.
+    fn set_brightness_smooth(&self, target: u64, duration_ms: u64) -> io::Result<()>;
+
+    /// Clamps a brightness between the minimum and maximum brightness.
+    fn clamp_brightness(&self, brightness: u64) -> io::Result<u64>;
+
+    /// Raises or lowers the brightness by `percent` of the maximum brightness, returning the new
+    /// brightness as a percentage. The brightness never falls below the minimum brightness.
+    fn step_brightness(&self, percent: i16) -> io::Result<u8>;
+}
+
+impl BacklightExt for Backlight {
+    fn set_brightness_smooth(&self, target: u64, duration_ms: u64) -> io::Result<()> {
+        let target = self.clamp_brightness(target)?;
+        let start = self.actual_brightness()?;
+        if start == target {
+            return Ok(());
+        }
+
+        fade(self, start, target, duration_ms)
+    }
+
+    fn clamp_brightness(&self, brightness: u64) -> io::Result<u64> {
+        let max = self.max_brightness()?;
+        Ok(cmp::min(cmp::max(brightness, min_brightness(max)), max))
+    }
+
+    fn step_brightness(&self, percent: i16) -> io::Result<u8> {
+        let max = self.max_brightness()? as i64;
+        let current = self.actual_brightness()? as i64;
+        let floor = min_brightness(max as u64) as i64;
+
+        let step = max * i64::from(percent) / 100;
+        let step = if step == 0 { i64::from(percent.signum()) } else { step };
+        let target = cmp::min(cmp::max(current + step, floor), max);
+
+        self.set_brightness(target as u64)?;
+        Ok((target * 100 / max) as u8)
+    }
+}
+
+/// Parses a color written as 6 hex digits, with an optional leading `#`.
+pub fn parse_color(color: &str) -> Result<u32, String> {
+    let hex = color.trim_start_matches('#');
+    if hex.len() != 6 {
+        return Err(format!("color '{}' is not 6 hex digits", color));
+    }
+
+    u32::from_str_radix(hex, 16).map_err(|why| format!("color '{}' is invalid: {}", color, why))
+}
+
+pub trait KeyboardBacklightExt {
+    /// Whether the keyboard has an RGB backlight zone with the given name.
+    fn has_color_zone(&self, zone: &str) -> bool;
+
+    fn get_color(&self, zone: &str) -> io::Result<u32>;
+
+    fn set_color(&self, zone: &str, rgb: u32) -> io::Result<()>;
+
+    /// Raises or lowers the brightness by a fifth of the maximum brightness, returning the new
+    /// brightness as a percentage. The brightness is clamped between off and the maximum.
+    fn step_level(&self, up: bool) -> io::Result<u8>;
+
+    /// Fades from the current brightness to `target` over `duration_ms`, writing an
+    /// intermediate value every 16 ms, until the brightness is changed by something else.
+    /// Unlike display backlights, the target may be off.
+    fn fade_to(&self, target: u64, duration_ms: u64) -> io::Result<()>;
+}
+
+impl KeyboardBacklightExt for Leds {
+    fn has_color_zone(&self, zone: &str) -> bool {
+        COLOR_ZONES.contains(&zone) && self.path().join(format!("color_{}", zone)).exists()
+    }
+
+    fn get_color(&self, zone: &str) -> io::Result<u32> {
+        let value = self.read_file(color_file(self, zone)?)?;
+        u32::from_str_radix(value.trim(), 16)
+            .map_err(|why| io::Error::new(io::ErrorKind::InvalidData, why))
+    }
+
+    fn set_color(&self, zone: &str, rgb: u32) -> io::Result<()> {
+        self.write_file(color_file(self, zone)?, format!("{:06X}", rgb & 0xFF_FFFF))
+    }
+
+    fn step_level(&self, up: bool) -> io::Result<u8> {
+        let max = self.max_brightness()?;
+        if max == 0 {
+            return Ok(0);
+        }
+
+        let current = self.brightness()?;
+        let step = (max + KEYBOARD_STEPS - 1) / KEYBOARD_STEPS;
+        let target = if up { cmp::min(current + step, max) } else { current.saturating_sub(step) };
+
+        self.set_brightness(target)?;
+        Ok((target * 100 / max) as u8)
+    }
+
+    fn fade_to(&self, target: u64, duration_ms: u64) -> io::Result<()> {
+        let target = cmp::min(target, self.max_brightness()?);
+        let start = self.brightness()?;
+        if start == target {
+            return Ok(());
+        }
+
+        fade(self, start, target, duration_ms)
+    }
+}
+
+fn color_file(leds: &Leds, zone: &str) -> io::Result<String> {
+    if leds.has_color_zone(zone) {
+        Ok(format!("color_{}", zone))
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("keyboard backlight {} does not support color zone '{}'", leds.id(), zone),
+        ))
+    }
+}
@@ -138,3 +138,20 @@ pub(crate) fn set_charge_thresholds((start, end): (u8, u8)) -> Result<(), String
 
     Ok(())
 }
+
+/// The id of the profile whose thresholds match the current thresholds, or `custom`.
+pub(crate) fn get_charge_profile() -> Result<String, String> {
+    let (start, end) = get_charge_thresholds()?;
+    Ok(get_charge_profiles()
+        .into_iter()
+        .find(|p| p.start == start && p.end == end)
+        .map_or_else(|| "custom".to_string(), |p| p.id))
+}
+
+pub(crate) fn set_charge_profile(id: &str) -> Result<(), String> {
+    let profile = get_charge_profiles()
+        .into_iter()
+        .find(|p| p.id == id)
+        .ok_or_else(|| format!("No such profile '{}'", id))?;
+    set_charge_thresholds((profile.start, profile.end))
+}
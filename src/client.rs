@@ -1,24 +1,45 @@
-use crate::{charge_thresholds::ChargeProfile, err_str, Power, DBUS_IFACE, DBUS_NAME, DBUS_PATH};
+use crate::{
+    charge_thresholds::ChargeProfile,
+    err_str,
+    graphics::{GraphicsFunction, GraphicsMode, GraphicsPowerState},
+    power_supply, Power, DBUS_IFACE, DBUS_NAME, DBUS_PATH,
+};
 use clap::ArgMatches;
 use dbus::{
-    arg::Append,
-    blocking::{BlockingSender, Connection},
+    arg::{Append, AppendAll, IterAppend},
+    blocking::{
+        stdintf::org_freedesktop_dbus::PropertiesPropertiesChanged, BlockingSender, Connection,
+    },
+    channel::{BusType, Channel},
+    message::{MatchRule, SignalArgs},
     Message,
 };
-use intel_pstate::PState;
-use std::{io, time::Duration};
+use serde::Serialize;
+use std::{
+    cmp,
+    collections::HashMap,
+    io,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
 use sysfs_class::{Backlight, Brightness, Leds, SysClass};
 
 static TIMEOUT: u64 = 60 * 1000;
 
+// Switching graphics regenerates the initramfs, which may take several minutes.
+static GRAPHICS_TIMEOUT: u64 = 5 * 60 * 1000;
+
 pub struct PowerClient {
-    bus: Connection,
+    bus:     Connection,
+    timeout: Duration,
 }
 
 impl PowerClient {
-    pub fn new() -> Result<PowerClient, String> {
-        let bus = Connection::new_system().map_err(err_str)?;
-        Ok(PowerClient { bus })
+    pub fn new() -> Result<PowerClient, String> { Self::with_options(BusType::System, TIMEOUT) }
+
+    /// Connects to the daemon on the given bus, waiting up to `timeout_ms` for each reply.
+    pub fn with_options(bus_type: BusType, timeout_ms: u64) -> Result<PowerClient, String> {
+        let bus = Channel::get_private(bus_type).map(Connection::from).map_err(err_str)?;
+        Ok(PowerClient { bus, timeout: Duration::from_millis(timeout_ms) })
     }
 
     fn call_method<A: Append>(
@@ -26,23 +47,47 @@ impl PowerClient {
         method: &str,
         append: Option<A>,
     ) -> Result<Message, String> {
-        let mut m = Message::new_method_call(DBUS_NAME, DBUS_PATH, DBUS_IFACE, method)?;
-        if let Some(arg) = append {
-            m = m.append1(arg);
+        match append {
+            Some(arg) => self.call_method_args(method, (arg,)),
+            None => self.call_method_args(method, ()),
         }
+    }
 
-        let r = self.bus.send_with_reply_and_block(m, Duration::from_millis(TIMEOUT)).map_err(
-            |why| {
-                format!(
-                    "daemon returned an error message: \"{}\"",
-                    err_str(why.message().unwrap_or(""))
-                )
-            },
-        )?;
+    /// Calls a method with any number of arguments, rather than a single optional argument.
+    fn call_method_args<A: AppendAll>(&mut self, method: &str, args: A) -> Result<Message, String> {
+        let timeout = self.timeout;
+        self.call_method_timeout(method, args, timeout)
+    }
+
+    fn call_method_timeout<A: AppendAll>(
+        &mut self,
+        method: &str,
+        args: A,
+        timeout: Duration,
+    ) -> Result<Message, String> {
+        let mut m = Message::new_method_call(DBUS_NAME, DBUS_PATH, DBUS_IFACE, method)?;
+        args.append(&mut IterAppend::new(&mut m));
+
+        let r = self.bus.send_with_reply_and_block(m, timeout).map_err(|why| {
+            format!(
+                "daemon returned an error message: \"{}\"",
+                err_str(why.message().unwrap_or(""))
+            )
+        })?;
 
         Ok(r)
     }
 
+    fn switch_graphics_with(&mut self, method: &str, vendor: &str) -> Result<(), String> {
+        println!("setting graphics to {}", vendor);
+        let timeout = cmp::max(self.timeout, Duration::from_millis(GRAPHICS_TIMEOUT));
+        let r = self.call_method_timeout(method, (vendor,), timeout).map(|_| ());
+        if r.is_ok() {
+            println!("A reboot is required to apply the graphics change.");
+        }
+        r
+    }
+
     fn set_profile(&mut self, profile: &str) -> Result<(), String> {
         println!("setting power profile to {}", profile);
         self.call_method::<bool>(profile, None)?;
@@ -57,6 +102,18 @@ impl Power for PowerClient {
 
     fn battery(&mut self) -> Result<(), String> { self.set_profile("Battery") }
 
+    fn set_custom_profile(
+        &mut self,
+        min_perf_pct: u8,
+        max_perf_pct: u8,
+        no_turbo: bool,
+        governor: &str,
+    ) -> Result<(), String> {
+        println!("setting power profile to custom");
+        let args = (min_perf_pct, max_perf_pct, no_turbo, governor);
+        self.call_method_args("SetCustomProfile", args).map(|_| ())
+    }
+
     fn get_external_displays_require_dgpu(&mut self) -> Result<bool, String> {
         let r = self.call_method::<bool>("GetExternalDisplaysRequireDGPU", None)?;
         r.get1().ok_or_else(|| "return value not found".to_string())
@@ -67,9 +124,10 @@ impl Power for PowerClient {
         r.get1().ok_or_else(|| "return value not found".to_string())
     }
 
-    fn get_graphics(&mut self) -> Result<String, String> {
+    fn get_graphics(&mut self) -> Result<GraphicsMode, String> {
         let r = self.call_method::<bool>("GetGraphics", None)?;
-        r.get1().ok_or_else(|| "return value not found".to_string())
+        let mode: String = r.get1().ok_or_else(|| "return value not found".to_string())?;
+        mode.parse()
     }
 
     fn get_profile(&mut self) -> Result<String, String> {
@@ -83,12 +141,31 @@ impl Power for PowerClient {
     }
 
     fn set_graphics(&mut self, vendor: &str) -> Result<(), String> {
-        println!("setting graphics to {}", vendor);
-        let r = self.call_method::<&str>("SetGraphics", Some(vendor)).map(|_| ());
-        if r.is_ok() {
-            println!("reboot for changes to take effect");
-        }
-        r
+        self.switch_graphics_with("SetGraphics", vendor)
+    }
+
+    fn set_graphics_force(&mut self, vendor: &str) -> Result<(), String> {
+        self.switch_graphics_with("SetGraphicsForce", vendor)
+    }
+
+    fn set_graphics_dry_run(&mut self, vendor: &str) -> Result<Vec<String>, String> {
+        let r = self.call_method::<&str>("SetGraphicsDryRun", Some(vendor))?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn get_nvidia_version(&mut self) -> Result<String, String> {
+        let r = self.call_method::<bool>("GetNvidiaVersion", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn get_nvidia_fallback_enabled(&mut self) -> Result<String, String> {
+        let r = self.call_method::<bool>("GetNvidiaFallbackEnabled", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn get_nvidia_temperature(&mut self) -> Result<i32, String> {
+        let r = self.call_method::<bool>("GetNvidiaTemperature", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
     }
 
     fn get_graphics_power(&mut self) -> Result<bool, String> {
@@ -96,16 +173,70 @@ impl Power for PowerClient {
         r.get1().ok_or_else(|| "return value not found".to_string())
     }
 
+    fn get_graphics_power_state(&mut self) -> Result<GraphicsPowerState, String> {
+        let r = self.call_method::<bool>("GetGraphicsPowerState", None)?;
+        let state: String = r.get1().ok_or_else(|| "return value not found".to_string())?;
+        state.parse()
+    }
+
     fn set_graphics_power(&mut self, power: bool) -> Result<(), String> {
         println!("turning discrete graphics {}", if power { "on" } else { "off " });
         self.call_method::<bool>("SetGraphicsPower", Some(power)).map(|_| ())
     }
 
+    fn set_graphics_power_force(&mut self, power: bool) -> Result<(), String> {
+        println!("forcibly turning discrete graphics {}", if power { "on" } else { "off" });
+        self.call_method::<bool>("SetGraphicsPowerForce", Some(power)).map(|_| ())
+    }
+
+    fn set_graphics_power_for(&mut self, id: &str, power: bool) -> Result<(), String> {
+        println!("turning {} {}", id, if power { "on" } else { "off" });
+        self.call_method_args("SetGraphicsPowerFor", (id, power)).map(|_| ())
+    }
+
+    fn get_pstate(&mut self) -> Result<(u8, u8, bool), String> {
+        let r = self.call_method::<bool>("GetPState", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn get_boot_vga(&mut self) -> Result<String, String> {
+        let r = self.call_method::<bool>("GetBootVga", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn get_integrated_vendor(&mut self) -> Result<String, String> {
+        let r = self.call_method::<bool>("GetIntegratedVendor", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn get_graphics_power_blockers(&mut self) -> Result<Vec<(String, String, Vec<u32>)>, String> {
+        let r = self.call_method::<bool>("GetGraphicsPowerBlockers", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn list_graphics_functions(&mut self) -> Result<Vec<GraphicsFunction>, String> {
+        let r = self.call_method::<bool>("ListGraphicsFunctions", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn list_graphics_devices(&mut self) -> Result<Vec<(String, String, String)>, String> {
+        let r = self.call_method::<bool>("ListGraphicsDevices", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
     fn auto_graphics_power(&mut self) -> Result<(), String> {
         println!("setting discrete graphics to turn off when not in use");
         self.call_method::<bool>("AutoGraphicsPower", None).map(|_| ())
     }
 
+    fn set_graphics_runtime_pm(&mut self, enabled: bool) -> Result<(), String> {
+        self.call_method::<bool>("SetGraphicsRuntimePm", Some(enabled)).map(|_| ())
+    }
+
+    fn set_graphics_d3cold(&mut self, allowed: bool) -> Result<(), String> {
+        self.call_method::<bool>("SetGraphicsD3Cold", Some(allowed)).map(|_| ())
+    }
+
     fn get_charge_thresholds(&mut self) -> Result<(u8, u8), String> {
         let r = self.call_method::<bool>("GetChargeThresholds", None)?;
         r.get1().ok_or_else(|| "return value not found".to_string())
@@ -119,6 +250,291 @@ impl Power for PowerClient {
         let r = self.call_method::<bool>("GetChargeProfiles", None)?;
         r.get1().ok_or_else(|| "return value not found".to_string())
     }
+
+    fn get_charge_profile(&mut self) -> Result<String, String> {
+        let r = self.call_method::<bool>("GetChargeProfile", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn set_charge_profile(&mut self, profile: &str) -> Result<(), String> {
+        self.call_method::<&str>("SetChargeProfile", Some(profile)).map(|_| ())
+    }
+
+    fn get_cpu_frequencies(&mut self) -> Result<Vec<u32>, String> {
+        let r = self.call_method::<bool>("GetCpuFrequencies", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn get_thermal_state(&mut self) -> Result<(i32, bool), String> {
+        let r = self.call_method::<bool>("GetThermalState", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn get_temperatures(&mut self) -> Result<HashMap<String, i32>, String> {
+        let r = self.call_method::<bool>("GetTemperatures", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn get_cpu_temperature(&mut self) -> Result<i32, String> {
+        let r = self.call_method::<bool>("GetCpuTemperature", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn get_model(&mut self) -> Result<String, String> {
+        let r = self.call_method::<bool>("GetModel", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn get_firmware_versions(&mut self) -> Result<(String, String), String> {
+        let r = self.call_method::<bool>("GetFirmwareVersions", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn set_display_brightness(&mut self, percent: u8, fade_ms: u32) -> Result<(), String> {
+        self.call_method_args("SetDisplayBrightness", (percent, fade_ms)).map(|_| ())
+    }
+
+    fn increase_display_brightness(&mut self, step: u8) -> Result<u8, String> {
+        let r = self.call_method::<u8>("IncreaseDisplayBrightness", Some(step))?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn decrease_display_brightness(&mut self, step: u8) -> Result<u8, String> {
+        let r = self.call_method::<u8>("DecreaseDisplayBrightness", Some(step))?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn set_external_brightness(&mut self, display: u32, percent: u8) -> Result<(), String> {
+        self.call_method_args("SetExternalBrightness", (display, percent)).map(|_| ())
+    }
+
+    fn increase_keyboard_brightness(&mut self, device: i32) -> Result<u8, String> {
+        let r = self.call_method::<i32>("IncreaseKeyboardBrightness", Some(device))?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn decrease_keyboard_brightness(&mut self, device: i32) -> Result<u8, String> {
+        let r = self.call_method::<i32>("DecreaseKeyboardBrightness", Some(device))?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn set_keyboard_color(&mut self, zone: &str, color: &str) -> Result<(), String> {
+        self.call_method_args("SetKeyboardColor", (zone, color)).map(|_| ())
+    }
+
+    fn get_fan_speeds(&mut self) -> Result<Vec<u32>, String> {
+        let r = self.call_method::<bool>("GetFanSpeeds", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn set_fan_duty(&mut self, index: u32, percent: u8) -> Result<(), String> {
+        self.call_method_args("SetFanDuty", (index, percent)).map(|_| ())
+    }
+
+    fn set_fan_curve(&mut self, curve: &str) -> Result<(), String> {
+        self.call_method::<&str>("SetFanCurve", Some(curve)).map(|_| ())
+    }
+
+    fn set_auto_profile_switch(&mut self, enabled: bool) -> Result<(), String> {
+        self.call_method::<bool>("SetAutoProfileSwitch", Some(enabled)).map(|_| ())
+    }
+
+    fn reload_config(&mut self) -> Result<(), String> {
+        self.call_method::<bool>("ReloadConfig", None).map(|_| ())
+    }
+
+    fn get_turbo(&mut self) -> Result<bool, String> {
+        let r = self.call_method::<bool>("GetTurbo", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn set_turbo(&mut self, enabled: bool) -> Result<(), String> {
+        println!("turning turbo {}", if enabled { "on" } else { "off" });
+        self.call_method::<bool>("SetTurbo", Some(enabled)).map(|_| ())
+    }
+
+    fn get_smt(&mut self) -> Result<bool, String> {
+        let r = self.call_method::<bool>("GetSmt", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn set_smt(&mut self, enabled: bool) -> Result<(), String> {
+        println!("turning SMT {}", if enabled { "on" } else { "off" });
+        self.call_method::<bool>("SetSmt", Some(enabled)).map(|_| ())
+    }
+
+    fn set_auto_brightness(&mut self, enabled: bool) -> Result<(), String> {
+        println!("turning auto brightness {}", if enabled { "on" } else { "off" });
+        self.call_method::<bool>("SetAutoBrightness", Some(enabled)).map(|_| ())
+    }
+
+    fn set_keyboard_timeout(&mut self, seconds: u32) -> Result<(), String> {
+        self.call_method::<u32>("SetKeyboardTimeout", Some(seconds)).map(|_| ())
+    }
+
+    fn set_online_cores(&mut self, count: u32) -> Result<(), String> {
+        println!("setting online cores to {}", count);
+        self.call_method::<u32>("SetOnlineCores", Some(count)).map(|_| ())
+    }
+
+    fn get_platform_profile(&mut self) -> Result<String, String> {
+        let r = self.call_method::<bool>("GetPlatformProfile", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn get_battery_health(&mut self) -> Result<(u8, u32), String> {
+        let r = self.call_method::<bool>("GetBatteryHealth", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn get_power_draw(&mut self) -> Result<f64, String> {
+        let r = self.call_method::<bool>("GetPowerDraw", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn get_battery_status(&mut self) -> Result<(String, u64), String> {
+        let r = self.call_method::<bool>("GetBatteryStatus", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn get_available_governors(&mut self) -> Result<Vec<String>, String> {
+        let r = self.call_method::<bool>("GetAvailableGovernors", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn get_governors(&mut self) -> Result<Vec<String>, String> {
+        let r = self.call_method::<bool>("GetGovernors", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn set_governor(&mut self, governor: &str) -> Result<(), String> {
+        self.call_method::<&str>("SetGovernor", Some(governor)).map(|_| ())
+    }
+
+    fn set_core_governor(&mut self, core: u32, governor: &str) -> Result<(), String> {
+        self.call_method_args("SetCoreGovernor", (core, governor)).map(|_| ())
+    }
+
+    fn set_cpu_frequency(&mut self, core: u32, frequency: u32) -> Result<(), String> {
+        self.call_method_args("SetCpuFrequency", (core, frequency)).map(|_| ())
+    }
+
+    fn get_perf_percent(&mut self) -> Result<(u8, u8), String> {
+        let r = self.call_method::<bool>("GetPerfPercent", None)?;
+        r.get1().ok_or_else(|| "return value not found".to_string())
+    }
+
+    fn set_min_perf_percent(&mut self, percent: u8) -> Result<(), String> {
+        self.call_method::<u8>("SetMinPerfPercent", Some(percent)).map(|_| ())
+    }
+
+    fn set_max_perf_percent(&mut self, percent: u8) -> Result<(), String> {
+        self.call_method::<u8>("SetMaxPerfPercent", Some(percent)).map(|_| ())
+    }
+}
+
+/// The output of `profile --json`. These field names are relied upon by scripts, and must not
+/// be changed.
+#[derive(Serialize)]
+struct ProfileJson {
+    /// The active power profile, or `null` if the daemon could not be reached.
+    profile:             Option<String>,
+    /// The active graphics mode, or `null` if it could not be determined.
+    graphics:            Option<String>,
+    /// The intel_pstate values, or `null` if intel_pstate is not in use.
+    pstate:              Option<PStateJson>,
+    backlights:          Vec<BrightnessJson>,
+    keyboard_backlights: Vec<BrightnessJson>,
+}
+
+#[derive(Serialize)]
+struct PStateJson {
+    min_perf_pct: u8,
+    max_perf_pct: u8,
+    no_turbo:     bool,
+}
+
+#[derive(Serialize)]
+struct BrightnessJson {
+    id:             String,
+    brightness:     u64,
+    max_brightness: u64,
+}
+
+fn profile_json(client: &mut PowerClient) -> io::Result<()> {
+    let mut backlights = Vec::new();
+    for backlight in Backlight::iter() {
+        let backlight = backlight?;
+        backlights.push(BrightnessJson {
+            id:             backlight.id().to_owned(),
+            brightness:     backlight.actual_brightness()?,
+            max_brightness: backlight.max_brightness()?,
+        });
+    }
+
+    let mut keyboard_backlights = Vec::new();
+    for backlight in Leds::iter_keyboards() {
+        let backlight = backlight?;
+        keyboard_backlights.push(BrightnessJson {
+            id:             backlight.id().to_owned(),
+            brightness:     backlight.brightness()?,
+            max_brightness: backlight.max_brightness()?,
+        });
+    }
+
+    let output =
+        ProfileJson {
+            profile: client.get_profile().ok(),
+            graphics: client.get_graphics().ok().map(|mode| mode.to_string()),
+            pstate: client.get_pstate().ok().map(|(min_perf_pct, max_perf_pct, no_turbo)| {
+                PStateJson { min_perf_pct, max_perf_pct, no_turbo }
+            }),
+            backlights,
+            keyboard_backlights,
+        };
+
+    let json = serde_json::to_string_pretty(&output)
+        .map_err(|why| io::Error::new(io::ErrorKind::Other, why))?;
+    println!("{}", json);
+    Ok(())
+}
+
+/// Collapses identical governors, in the order of the first core using each, such as
+/// `performance ×8`. Offline cores are skipped.
+fn governor_summary(governors: Vec<String>) -> Option<String> {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    for governor in governors.into_iter().filter(|governor| !governor.is_empty()) {
+        match counts.iter_mut().find(|(name, _)| *name == governor) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((governor, 1)),
+        }
+    }
+
+    if counts.is_empty() {
+        return None;
+    }
+
+    let summary = counts
+        .iter()
+        .map(|(name, count)| format!("{} ×{}", name, count))
+        .collect::<Vec<_>>()
+        .join(", ");
+    Some(summary)
+}
+
+fn cpu_status(client: &mut PowerClient) -> Result<(), String> {
+    let governors = client.get_governors()?;
+    println!("Governor: {}", governor_summary(governors).as_deref().unwrap_or("unknown"));
+
+    let (min, max) = client.get_perf_percent()?;
+    println!("Frequency: {}% - {}%", min, max);
+
+    match client.get_turbo() {
+        Ok(turbo) => println!("Turbo: {}", if turbo { "on" } else { "off" }),
+        Err(why) => println!("Turbo: {}", why),
+    }
+
+    Ok(())
 }
 
 fn profile(client: &mut PowerClient) -> io::Result<()> {
@@ -126,13 +542,64 @@ fn profile(client: &mut PowerClient) -> io::Result<()> {
     let profile = profile.as_ref().map_or("?", |s| s.as_str());
     println!("Power Profile: {}", profile);
 
-    if let Ok(values) = PState::new().and_then(|pstate| pstate.values()) {
-        println!(
-            "CPU: {}% - {}%, {}",
-            values.min_perf_pct,
-            values.max_perf_pct,
-            if values.no_turbo { "No Turbo" } else { "Turbo" }
-        );
+    if let Ok(platform_profile) = client.get_platform_profile() {
+        println!("Platform Profile: {}", platform_profile);
+    }
+
+    if let Ok((min, max, no_turbo)) = client.get_pstate() {
+        println!("CPU: {}% - {}%, {}", min, max, if no_turbo { "No Turbo" } else { "Turbo" });
+    }
+
+    if let Some(summary) = client.get_governors().ok().and_then(governor_summary) {
+        println!("Governors: {}", summary);
+    }
+
+    if let Ok(frequencies) = client.get_cpu_frequencies() {
+        for (core, frequency) in frequencies.iter().enumerate() {
+            if *frequency != 0 {
+                println!("Core {}: {} MHz", core, frequency / 1000);
+            }
+        }
+    }
+
+    if let Ok((temp, throttling)) = client.get_thermal_state() {
+        println!("Hottest Thermal Zone: {:.1}°C", f64::from(temp) / 1000.0);
+        println!("Throttling: {}", if throttling { "yes" } else { "no" });
+    }
+
+    if let Ok(temp) = client.get_cpu_temperature() {
+        println!("CPU temp: {:.1}°C", f64::from(temp) / 1000.0);
+    }
+
+    if let Ok(rpms) = client.get_fan_speeds() {
+        for (fan, rpm) in rpms.iter().enumerate() {
+            println!("Fan {}: {} RPM", fan, rpm);
+        }
+    }
+
+    if let Ok((health, cycles)) = client.get_battery_health() {
+        println!("Battery Health: {}%, {} cycles", health, cycles);
+    }
+
+    if let Ok((status, seconds)) = client.get_battery_status() {
+        let until = match status.as_str() {
+            "Charging" => " until full",
+            "Discharging" => " remaining",
+            _ => "",
+        };
+
+        if seconds == 0 || until.is_empty() {
+            println!("Battery Status: {}", status);
+        } else {
+            let minutes = seconds / 60;
+            println!("Battery Status: {}, {}:{:02}{}", status, minutes / 60, minutes % 60, until);
+        }
+    }
+
+    if power_supply::ac_online() == Some(false) {
+        if let Ok(watts) = client.get_power_draw() {
+            println!("System draw: {:.1} W", watts);
+        }
     }
 
     for backlight in Backlight::iter() {
@@ -162,6 +629,270 @@ fn profile(client: &mut PowerClient) -> io::Result<()> {
     Ok(())
 }
 
+/// Prints the name of each power profile that the daemon switches to, until interrupted.
+pub fn watch() -> Result<(), String> {
+    let bus = Connection::new_system().map_err(err_str)?;
+    let mut rule = MatchRule::new_signal(DBUS_NAME, "PowerProfileSwitch");
+    rule.path = Some(DBUS_PATH.into());
+
+    bus.add_match(rule, |(profile,): (String,), _, _| {
+        println!("{}", profile);
+        true
+    })
+    .map_err(err_str)?;
+
+    loop {
+        bus.process(Duration::from_millis(1000)).map_err(err_str)?;
+    }
+}
+
+/// The current UTC time of day, formatted as `HH:MM:SS`.
+fn timestamp() -> String {
+    let secs = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs()) % 86400;
+    format!("{:02}:{:02}:{:02}", secs / 3600, secs / 60 % 60, secs % 60)
+}
+
+/// Aborts powering off the graphics if a function is still in use, before the daemon begins
+/// unbinding and removing devices. When forced, the processes are only listed as a warning.
+fn check_graphics_power_blockers(client: &mut PowerClient, force: bool) -> Result<(), String> {
+    let blockers = client.get_graphics_power_blockers()?;
+    if blockers.is_empty() {
+        return Ok(());
+    }
+
+    if force {
+        println!("The discrete graphics are in use, powering off anyway:");
+    } else {
+        println!("The discrete graphics are in use, close these processes and try again:");
+    }
+
+    for (func, driver, pids) in blockers {
+        let pids = pids.iter().map(u32::to_string).collect::<Vec<_>>().join(", ");
+        println!("    {} ({}): {}", func, driver, pids);
+    }
+
+    if force {
+        Ok(())
+    } else {
+        Err("graphics power off aborted".into())
+    }
+}
+
+fn graphics_power(client: &mut PowerClient, matches: &ArgMatches) -> Result<(), String> {
+    let force = matches.is_present("force");
+    if matches.value_of("state") == Some("off") {
+        check_graphics_power_blockers(client, force)?;
+    }
+
+    match (matches.value_of("state"), matches.value_of("device")) {
+        (Some("off"), Some(id)) => client.set_graphics_power_for(id, false),
+        (Some("on"), Some(id)) => client.set_graphics_power_for(id, true),
+        (_, Some(_)) => Err("--device requires a state of on or off".to_string()),
+        (Some("auto"), None) => client.auto_graphics_power(),
+        (Some("off"), None) if force => client.set_graphics_power_force(false),
+        (Some("off"), None) => client.set_graphics_power(false),
+        (Some("on"), None) => client.set_graphics_power(true),
+        _ => {
+            println!("{} (discrete)", client.get_graphics_power_state()?);
+            Ok(())
+        }
+    }
+}
+
+/// Prints each signal emitted by the daemon with a timestamp, along with the CPU pstate and
+/// temperature once every `interval`, until interrupted.
+fn monitor(client: &mut PowerClient, interval: Duration) -> Result<(), String> {
+    let bus = Connection::new_system().map_err(err_str)?;
+    let signal = |name| {
+        let mut rule = MatchRule::new_signal(DBUS_NAME, name);
+        rule.path = Some(DBUS_PATH.into());
+        rule
+    };
+
+    bus.add_match(signal("PowerProfileSwitch"), |(profile,): (String,), _, _| {
+        println!("[{}] profile switched to {}", timestamp(), profile);
+        true
+    })
+    .map_err(err_str)?;
+
+    bus.add_match(signal("GraphicsSwitchComplete"), |(reboot,): (bool,), _, _| {
+        let reboot = if reboot { ", reboot required" } else { "" };
+        println!("[{}] graphics switch complete{}", timestamp(), reboot);
+        true
+    })
+    .map_err(err_str)?;
+
+    bus.add_match(signal("HotPlugDetect"), |(port,): (u64,), _, _| {
+        println!("[{}] hotplug detected on port {}", timestamp(), port);
+        true
+    })
+    .map_err(err_str)?;
+
+    // Charge thresholds have no signal of their own, only a property change.
+    let path = DBUS_PATH.into();
+    let properties = PropertiesPropertiesChanged::match_rule(None, Some(&path)).static_clone();
+    bus.add_match(properties, |changed: PropertiesPropertiesChanged, _, _| {
+        let thresholds = changed
+            .changed_properties
+            .get("ChargeThresholds")
+            .filter(|_| changed.interface_name == DBUS_IFACE)
+            .and_then(|value| value.0.as_iter())
+            .map(|mut fields| {
+                (fields.next().and_then(|f| f.as_u64()), fields.next().and_then(|f| f.as_u64()))
+            });
+
+        if let Some((Some(start), Some(end))) = thresholds {
+            println!("[{}] charge thresholds changed to {}% - {}%", timestamp(), start, end);
+        }
+
+        true
+    })
+    .map_err(err_str)?;
+
+    // Processing returns early whenever a message arrives, so the interval is timed rather
+    // than counted in iterations.
+    let mut last_status: Option<Instant> = None;
+    loop {
+        if last_status.map_or(true, |at| at.elapsed() >= interval) {
+            last_status = Some(Instant::now());
+            let mut status = Vec::new();
+            if let Ok((min, max, no_turbo)) = client.get_pstate() {
+                let turbo = if no_turbo { ", No Turbo" } else { "" };
+                status.push(format!("CPU {}% - {}%{}", min, max, turbo));
+            }
+
+            if let Ok((temp, throttling)) = client.get_thermal_state() {
+                status.push(format!("{:.1}°C", f64::from(temp) / 1000.0));
+                if throttling {
+                    status.push("throttling".into());
+                }
+            }
+
+            if !status.is_empty() {
+                println!("[{}] {}", timestamp(), status.join(", "));
+            }
+        }
+
+        bus.process(Duration::from_millis(1000)).map_err(err_str)?;
+    }
+}
+
+fn switch_graphics(
+    client: &mut PowerClient,
+    vendor: &str,
+    matches: &ArgMatches,
+) -> Result<(), String> {
+    if !client.get_switchable()? {
+        println!("switchable graphics not available");
+        return Ok(());
+    }
+
+    if !matches.is_present("dry-run") {
+        return if matches.is_present("force") {
+            client.set_graphics_force(vendor)
+        } else {
+            client.set_graphics(vendor)
+        };
+    }
+
+    println!("actions needed to set graphics to {}:", vendor);
+    for action in client.set_graphics_dry_run(vendor)? {
+        println!("  {}", action.replace('\n', "\n    "));
+    }
+
+    Ok(())
+}
+
+/// Prints every piece of graphics state that the daemon reports, for bug reports. Values which
+/// cannot be queried are reported as unavailable, rather than aborting the report.
+fn graphics_status(client: &mut PowerClient) -> Result<(), String> {
+    fn or_unavailable(result: Result<String, String>) -> String {
+        result.unwrap_or_else(|why| format!("unavailable ({})", why))
+    }
+
+    let switchable = client.get_switchable()?;
+    println!("Switchable: {}", if switchable { "yes" } else { "no" });
+    println!("Mode: {}", or_unavailable(client.get_graphics().map(|mode| mode.to_string())));
+    println!("Default mode: {}", or_unavailable(client.get_default_graphics()));
+    println!(
+        "Discrete power: {}",
+        or_unavailable(client.get_graphics_power_state().map(|state| state.to_string()))
+    );
+
+    let version = client.get_nvidia_version().unwrap_or_default();
+    println!("NVIDIA driver: {}", if version.is_empty() { "not loaded" } else { version.as_str() });
+    println!("Boot VGA: {}", or_unavailable(client.get_boot_vga()));
+    println!("Integrated: {}", or_unavailable(client.get_integrated_vendor()));
+    println!("NVIDIA fallback service: {}", or_unavailable(client.get_nvidia_fallback_enabled()));
+
+    println!("Devices:");
+    let devices = client.list_graphics_devices()?;
+    let functions = client.list_graphics_functions()?;
+    for (id, vendor, state) in devices {
+        println!("  {}: {} ({})", id, vendor, state);
+        for (_, func, class, vendor, driver) in functions.iter().filter(|func| func.0 == id) {
+            let driver = if driver.is_empty() { "no driver" } else { driver.as_str() };
+            println!("    {}: class {} vendor {} ({})", func, class, vendor, driver);
+        }
+    }
+
+    Ok(())
+}
+
+fn graphics(client: &mut PowerClient, matches: &ArgMatches) -> Result<(), String> {
+    match matches.subcommand() {
+        ("compute", Some(matches)) => switch_graphics(client, "compute", matches),
+        ("hybrid", Some(matches)) => switch_graphics(client, "hybrid", matches),
+        ("integrated", Some(matches)) | ("intel", Some(matches)) => {
+            switch_graphics(client, "integrated", matches)
+        }
+        ("integrated-pm", Some(matches)) => switch_graphics(client, "integrated-pm", matches),
+        ("nvidia", Some(matches)) => switch_graphics(client, "nvidia", matches),
+        ("status", _) => graphics_status(client),
+        ("switchable", _) => {
+            if client.get_switchable()? {
+                println!("switchable");
+            } else {
+                println!("not switchable");
+            }
+            Ok(())
+        }
+        ("functions", _) => {
+            for (dev, func, class, vendor, driver) in client.list_graphics_functions()? {
+                let driver = if driver.is_empty() { "no driver" } else { driver.as_str() };
+                println!("{}: {} class {} vendor {} ({})", dev, func, class, vendor, driver);
+            }
+            Ok(())
+        }
+        ("list", _) => {
+            for (id, vendor, state) in client.list_graphics_devices()? {
+                println!("{}: {} ({})", id, vendor, state);
+            }
+
+            if let Ok(vendor) = client.get_boot_vga() {
+                println!("boot VGA: {}", vendor);
+            }
+            Ok(())
+        }
+        ("power", Some(matches)) => graphics_power(client, matches),
+        _ => {
+            let mode = client.get_graphics()?;
+            let version = if mode == GraphicsMode::Integrated {
+                String::new()
+            } else {
+                client.get_nvidia_version()?
+            };
+
+            if version.is_empty() {
+                println!("{}", mode);
+            } else {
+                println!("{} (NVIDIA {})", mode, version);
+            }
+            Ok(())
+        }
+    }
+}
+
 pub fn client(subcommand: &str, matches: &ArgMatches) -> Result<(), String> {
     let mut client = PowerClient::new()?;
 
@@ -170,39 +901,10 @@ pub fn client(subcommand: &str, matches: &ArgMatches) -> Result<(), String> {
             Some("balanced") => client.balanced(),
             Some("battery") => client.battery(),
             Some("performance") => client.performance(),
+            _ if matches.is_present("json") => profile_json(&mut client).map_err(err_str),
             _ => profile(&mut client).map_err(err_str),
         },
-        "graphics" => match matches.subcommand() {
-            ("compute", _) => client.set_graphics("compute"),
-            ("hybrid", _) => client.set_graphics("hybrid"),
-            ("integrated", _) | ("intel", _) => client.set_graphics("integrated"),
-            ("nvidia", _) => client.set_graphics("nvidia"),
-            ("switchable", _) => {
-                if client.get_switchable()? {
-                    println!("switchable");
-                } else {
-                    println!("not switchable");
-                }
-                Ok(())
-            }
-            ("power", Some(matches)) => match matches.value_of("state") {
-                Some("auto") => client.auto_graphics_power(),
-                Some("off") => client.set_graphics_power(false),
-                Some("on") => client.set_graphics_power(true),
-                _ => {
-                    if client.get_graphics_power()? {
-                        println!("on (discrete)");
-                    } else {
-                        println!("off (discrete)");
-                    }
-                    Ok(())
-                }
-            },
-            _ => {
-                println!("{}", client.get_graphics()?);
-                Ok(())
-            }
-        },
+        "graphics" => graphics(&mut client, matches),
         "charge-thresholds" => {
             let profiles = client.get_charge_profiles()?;
 
@@ -241,6 +943,113 @@ pub fn client(subcommand: &str, matches: &ArgMatches) -> Result<(), String> {
 
             Ok(())
         }
+        "backlight" => {
+            let (subcommand, matches) = matches.subcommand();
+            let step = matches
+                .and_then(|m| m.value_of("step"))
+                .map_or(Ok(5), |s| s.parse::<u8>())
+                .map_err(err_str)?;
+            let percent = match subcommand {
+                "up" => client.increase_display_brightness(step)?,
+                "down" => client.decrease_display_brightness(step)?,
+                _ => unreachable!(),
+            };
+            println!("{}%", percent);
+            Ok(())
+        }
+        "keyboard-backlight" => {
+            let (subcommand, matches) = matches.subcommand();
+            let device = matches
+                .and_then(|m| m.value_of("device"))
+                .map_or(Ok(-1), |s| s.parse::<i32>())
+                .map_err(err_str)?;
+            let percent = match subcommand {
+                "up" => client.increase_keyboard_brightness(device)?,
+                "down" => client.decrease_keyboard_brightness(device)?,
+                _ => unreachable!(),
+            };
+            println!("{}%", percent);
+            Ok(())
+        }
+        "cpu" => match matches.subcommand() {
+            ("governor", Some(matches)) => {
+                let governor = matches.value_of("name").unwrap();
+                match matches.value_of("core") {
+                    Some(core) => {
+                        client.set_core_governor(core.parse().map_err(err_str)?, governor)
+                    }
+                    None => client.set_governor(governor),
+                }
+            }
+            ("governors", _) => {
+                for governor in client.get_available_governors()? {
+                    println!("{}", governor);
+                }
+                Ok(())
+            }
+            ("turbo", Some(matches)) => match matches.value_of("state") {
+                Some("on") => client.set_turbo(true),
+                Some("off") => client.set_turbo(false),
+                _ => {
+                    println!("{}", if client.get_turbo()? { "on" } else { "off" });
+                    Ok(())
+                }
+            },
+            ("min", Some(matches)) => {
+                let percent =
+                    matches.value_of("percent").unwrap().parse::<u8>().map_err(err_str)?;
+                client.set_min_perf_percent(percent)
+            }
+            ("max", Some(matches)) => {
+                let percent =
+                    matches.value_of("percent").unwrap().parse::<u8>().map_err(err_str)?;
+                client.set_max_perf_percent(percent)
+            }
+            _ => cpu_status(&mut client),
+        },
+        "info" => {
+            let (bios, ec) = client.get_firmware_versions()?;
+            println!("Model: {}", client.get_model()?);
+            println!("BIOS Version: {}", bios);
+            println!("EC Version: {}", if ec.is_empty() { "unknown" } else { &ec });
+            Ok(())
+        }
+        "monitor" => {
+            let interval = matches
+                .value_of("interval")
+                .map_or(Ok(5), |s| s.parse::<u64>())
+                .map_err(err_str)?;
+            monitor(&mut client, Duration::from_secs(interval))
+        }
+        "reload" => {
+            client.reload_config()?;
+            println!("Reloaded the daemon configuration");
+            Ok(())
+        }
+        "temps" => {
+            let mut temps: Vec<_> = client.get_temperatures()?.into_iter().collect();
+            temps.sort();
+            let width = temps.iter().map(|(name, _)| name.len()).max().unwrap_or(0);
+            for (name, temp) in temps {
+                println!("{:width$}  {:>6.1}°C", name, f64::from(temp) / 1000.0, width = width);
+            }
+            Ok(())
+        }
+        "smt" => match matches.value_of("state") {
+            Some("on") => client.set_smt(true),
+            Some("off") => client.set_smt(false),
+            _ => {
+                println!("{}", if client.get_smt()? { "on" } else { "off" });
+                Ok(())
+            }
+        },
+        "charge-profile" => match matches.value_of("profile") {
+            Some(profile) => client.set_charge_profile(profile),
+            None => {
+                println!("{}", client.get_charge_profile()?);
+                Ok(())
+            }
+        },
         _ => Err(format!("unknown sub-command {}", subcommand)),
     }
 }
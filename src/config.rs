@@ -0,0 +1,156 @@
+use crate::{backlight, cpufreq};
+use serde::Deserialize;
+use std::{fs, io};
+
+const CONFIG_PATH: &str = "/etc/system76-power/config.toml";
+
+/// Settings for the daemon, read from `/etc/system76-power/config.toml` at startup. Any key
+/// that is missing keeps its default value.
+#[derive(Debug, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct Config {
+    /// The power profile applied at startup: `battery`, `balanced`, or `performance`.
+    pub default_profile:       String,
+    /// The share of the maximum CPU frequency permitted by the powersave configuration.
+    pub powersave_max_percent: u8,
+    /// Whether to switch profiles when the AC adapter is plugged in or unplugged.
+    pub auto_profile_switch:   bool,
+    /// The id of a charge profile to apply at startup, such as `max_lifespan`.
+    pub charge_profile:        Option<String>,
+    /// The lowest display brightness that clients may set, as a percentage of the maximum.
+    pub min_brightness:        u8,
+    /// Pairs of ambient illuminance in lux and display brightness percentage, used by auto
+    /// brightness.
+    pub auto_brightness_curve: Vec<(u32, u8)>,
+    /// The profile switched to before suspending, and restored from after resuming, or `none`
+    /// to keep the current profile.
+    pub sleep_profile:         String,
+    /// Whether to switch to the battery profile when the lid is closed while on battery, and
+    /// back when it is opened.
+    pub lid_close_battery:     bool,
+    /// Whether switching graphics modes regenerates the initramfs.
+    pub regenerate_initramfs:  bool,
+    /// A script run with the new profile name as its argument after each successful profile
+    /// switch.
+    pub on_profile_change:     Option<String>,
+    /// The battery percentage below which the keyboard backlight is turned off while on
+    /// battery, until the AC adapter is plugged in or the battery charges past it again.
+    pub low_battery_percent:   Option<u8>,
+    /// Whether to also switch to the battery profile while the battery is low.
+    pub low_battery_profile:   bool,
+    /// Settings applied when switching to each power profile.
+    pub profiles:              ProfilesConfig,
+}
+
+/// Settings for each power profile, read from the `[profiles.battery]`, `[profiles.balanced]`,
+/// and `[profiles.performance]` tables.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ProfilesConfig {
+    pub battery:     ProfileConfig,
+    pub balanced:    ProfileConfig,
+    pub performance: ProfileConfig,
+}
+
+impl ProfilesConfig {
+    /// The settings of the profile with the given name, such as `Battery`.
+    pub fn get(&self, profile: &str) -> Option<&ProfileConfig> {
+        match profile.to_lowercase().as_str() {
+            "battery" => Some(&self.battery),
+            "balanced" => Some(&self.balanced),
+            "performance" => Some(&self.performance),
+            _ => None,
+        }
+    }
+}
+
+/// Settings applied when switching to a profile. Backlight brightness is a percentage of the
+/// maximum, and backlights without a value are left unchanged. The cpufreq governor and energy
+/// performance preference replace the defaults of the profile.
+#[derive(Clone, Debug, Default, Deserialize, PartialEq)]
+#[serde(default)]
+pub struct ProfileConfig {
+    pub backlight:                     Option<u8>,
+    pub keyboard_backlight:            Option<u8>,
+    pub governor:                      Option<String>,
+    pub energy_performance_preference: Option<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            default_profile:       "balanced".into(),
+            powersave_max_percent: cpufreq::POWERSAVE_MAX_PERCENT,
+            auto_profile_switch:   false,
+            charge_profile:        None,
+            min_brightness:        backlight::MIN_BRIGHTNESS_PERCENT,
+            auto_brightness_curve: vec![(0, 10), (50, 30), (200, 50), (1000, 80), (5000, 100)],
+            sleep_profile:         "battery".into(),
+            lid_close_battery:     false,
+            regenerate_initramfs:  true,
+            on_profile_change:     None,
+            low_battery_percent:   None,
+            low_battery_profile:   false,
+            profiles:              ProfilesConfig::default(),
+        }
+    }
+}
+
+impl Config {
+    /// Loads the configuration file, falling back to the defaults if it is missing or invalid.
+    pub fn load() -> Self {
+        Self::try_load().unwrap_or_else(|why| {
+            log::error!("{}, using defaults", why);
+            Config::default()
+        })
+    }
+
+    /// Loads the configuration file, falling back to the defaults only if it is missing. A file
+    /// which cannot be read or parsed is an error.
+    pub fn try_load() -> Result<Self, String> {
+        let data = match fs::read_to_string(CONFIG_PATH) {
+            Ok(data) => data,
+            Err(ref why) if why.kind() == io::ErrorKind::NotFound => {
+                log::debug!("{} not found, using defaults", CONFIG_PATH);
+                return Ok(Config::default());
+            }
+            Err(why) => return Err(format!("failed to read {}: {}", CONFIG_PATH, why)),
+        };
+
+        Self::parse(&data).map_err(|why| format!("failed to parse {}: {}", CONFIG_PATH, why))
+    }
+
+    pub fn parse(data: &str) -> Result<Self, toml::de::Error> { toml::from_str(data) }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_partial() {
+        let config = Config::parse("default_profile = \"battery\"\nauto_profile_switch = true\n");
+        assert_eq!(
+            config.unwrap(),
+            Config {
+                default_profile: "battery".into(),
+                auto_profile_switch: true,
+                ..Config::default()
+            }
+        );
+        assert_eq!(Config::parse("").unwrap(), Config::default());
+        assert!(Config::parse("powersave_max_percent = \"high\"").is_err());
+
+        let config = Config::parse("[profiles.battery]\nbacklight = 40\n").unwrap();
+        assert_eq!(config.profiles.get("Battery").unwrap().backlight, Some(40));
+        assert_eq!(config.profiles.get("Battery").unwrap().keyboard_backlight, None);
+        assert_eq!(config.profiles.performance, ProfileConfig::default());
+
+        let config = Config::parse("[profiles.balanced]\ngovernor = \"powersave\"\n").unwrap();
+        assert_eq!(config.profiles.balanced.governor.as_deref(), Some("powersave"));
+        assert_eq!(config.profiles.balanced.energy_performance_preference, None);
+
+        let config = Config::parse("on_profile_change = \"/usr/local/bin/hook\"\n").unwrap();
+        assert_eq!(config.on_profile_change.as_deref(), Some("/usr/local/bin/hook"));
+    }
+}
@@ -0,0 +1,123 @@
+use crate::cpufreq;
+use intel_pstate::PState;
+use std::{fs, io, path::Path};
+
+const SMT_CONTROL: &str = "/sys/devices/system/cpu/smt/control";
+const SMT_ACTIVE: &str = "/sys/devices/system/cpu/smt/active";
+
+fn online_path(core: usize) -> String { format!("/sys/devices/system/cpu/cpu{}/online", core) }
+
+fn unsupported(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, format!("{} is not supported on this system", what))
+}
+
+/// Whether simultaneous multithreading (hyperthreading) is active.
+pub fn get_smt() -> io::Result<bool> {
+    if !Path::new(SMT_ACTIVE).exists() {
+        return Err(unsupported("SMT"));
+    }
+
+    Ok(fs::read_to_string(SMT_ACTIVE)?.trim() == "1")
+}
+
+/// Enables or disables simultaneous multithreading (hyperthreading).
+pub fn set_smt(enabled: bool) -> io::Result<()> {
+    if !Path::new(SMT_CONTROL).exists() {
+        return Err(unsupported("SMT control"));
+    }
+
+    // The control is only writable while it is `on` or `off`. It may also be `forceoff` from
+    // the kernel command line, or `notsupported` and `notimplemented`.
+    match fs::read_to_string(SMT_CONTROL)?.trim() {
+        "on" | "off" => fs::write(SMT_CONTROL, if enabled { "on" } else { "off" }),
+        other => Err(io::Error::new(
+            io::ErrorKind::Other,
+            format!("SMT control cannot be changed while it is {}", other),
+        )),
+    }
+}
+
+fn pstate_error(why: intel_pstate::PStateError) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, why.to_string())
+}
+
+/// Whether turbo is enabled, through intel_pstate or the global cpufreq boost control.
+pub fn get_turbo() -> io::Result<bool> {
+    if let Ok(pstate) = PState::new() {
+        return pstate.no_turbo().map(|no_turbo| !no_turbo).map_err(pstate_error);
+    }
+
+    cpufreq::get_boost()?.ok_or_else(|| unsupported("turbo control"))
+}
+
+/// Enables or disables turbo, through intel_pstate or the global cpufreq boost control.
+pub fn set_turbo(enabled: bool) -> io::Result<()> {
+    if let Ok(pstate) = PState::new() {
+        return pstate.set_no_turbo(!enabled).map_err(pstate_error);
+    }
+
+    if cpufreq::set_boost(enabled)? {
+        Ok(())
+    } else {
+        Err(unsupported("turbo control"))
+    }
+}
+
+/// The minimum and maximum performance, as a percentage of the maximum frequency, through
+/// intel_pstate or the cpufreq frequency limits.
+pub fn get_perf_percent() -> io::Result<(u8, u8)> {
+    if let Ok(pstate) = PState::new() {
+        let values = pstate.values().map_err(pstate_error)?;
+        return Ok((values.min_perf_pct, values.max_perf_pct));
+    }
+
+    cpufreq::get_percent()
+}
+
+/// Sets the minimum performance, as a percentage of the maximum frequency, through
+/// intel_pstate or the cpufreq frequency limits.
+pub fn set_min_perf_percent(percent: u8) -> io::Result<()> {
+    if let Ok(pstate) = PState::new() {
+        return pstate.set_min_perf_pct(percent).map_err(pstate_error);
+    }
+
+    cpufreq::set_min_percent(percent)
+}
+
+/// Sets the maximum performance, as a percentage of the maximum frequency, through
+/// intel_pstate or the cpufreq frequency limits.
+pub fn set_max_perf_percent(percent: u8) -> io::Result<()> {
+    if let Ok(pstate) = PState::new() {
+        return pstate.set_max_perf_pct(percent).map_err(pstate_error);
+    }
+
+    cpufreq::set_max_percent(percent)
+}
+
+/// Sets the governor of every online core with cpufreq support.
+pub fn set_governor(governor: &str) -> io::Result<()> {
+    for core in cpufreq::cpufreq_cores() {
+        cpufreq::set_governor(core, governor)?;
+    }
+
+    Ok(())
+}
+
+/// Keeps the first `count` cores online and takes the remaining cores offline. cpu0 is never
+/// touched, so at least one core always stays online. Cores which the kernel does not permit
+/// to be taken offline are skipped.
+pub fn set_online_cores(count: usize) -> io::Result<()> {
+    for core in 1..cpufreq::num_cpus().unwrap_or(0) {
+        let path = online_path(core);
+        if !Path::new(&path).exists() {
+            continue;
+        }
+
+        fs::write(&path, if core < count { "1" } else { "0" })?;
+    }
+
+    Ok(())
+}
+
+/// Brings every core back online.
+pub fn online_all_cores() -> io::Result<()> { set_online_cores(usize::max_value()) }
@@ -0,0 +1,340 @@
+use std::{
+    cmp, fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU8, Ordering},
+};
+
+/// The default percentage of the maximum frequency permitted by the powersave configuration.
+pub const POWERSAVE_MAX_PERCENT: u8 = 50;
+
+// The percentage used by `powersave`, which may be changed by the daemon configuration.
+static POWERSAVE_CAP: AtomicU8 = AtomicU8::new(POWERSAVE_MAX_PERCENT);
+
+/// The global boost control of the amd_pstate and acpi-cpufreq drivers.
+const BOOST_PATH: &str = "/sys/devices/system/cpu/cpufreq/boost";
+
+fn cpufreq_path(core: usize) -> PathBuf {
+    PathBuf::from(format!("/sys/devices/system/cpu/cpu{}/cpufreq", core))
+}
+
+fn read_value(core: usize, file: &str) -> io::Result<String> {
+    fs::read_to_string(cpufreq_path(core).join(file)).map(|value| value.trim().to_owned())
+}
+
+fn write_value(core: usize, file: &str, value: &str) -> io::Result<()> {
+    fs::write(cpufreq_path(core).join(file), value)
+}
+
+fn read_frequency(core: usize, file: &str) -> io::Result<usize> {
+    read_value(core, file)?
+        .parse::<usize>()
+        .map_err(|why| io::Error::new(io::ErrorKind::InvalidData, why))
+}
+
+/// The number of CPUs that the kernel considers possible on this system. The list is usually a
+/// range such as `0-7`, or a single `0` on a system with one CPU. If it cannot be parsed, the
+/// processors in `/proc/cpuinfo` are counted instead.
+pub fn num_cpus() -> Option<usize> {
+    let info = fs::read_to_string("/sys/devices/system/cpu/possible").unwrap_or_default();
+    let last = info.trim().rsplit(|c| c == '-' || c == ',').next().unwrap_or_default();
+    if let Ok(max) = last.parse::<usize>() {
+        return Some(max + 1);
+    }
+
+    let cpuinfo = fs::read_to_string("/proc/cpuinfo").ok()?;
+    let count = cpuinfo.lines().filter(|line| line.starts_with("processor")).count();
+    log::warn!(
+        "failed to parse possible CPUs from '{}', using {} processors from /proc/cpuinfo",
+        info.trim(),
+        count
+    );
+
+    if count == 0 {
+        None
+    } else {
+        Some(count)
+    }
+}
+
+/// The cores which are currently online, parsed from a list of ranges such as `0-3,6,8-11`.
+pub fn online_cpus() -> Vec<usize> {
+    let info = fs::read_to_string("/sys/devices/system/cpu/online").unwrap_or_default();
+    let mut cores = Vec::new();
+    for range in info.trim().split(',').filter(|range| !range.is_empty()) {
+        let mut bounds = range.splitn(2, '-').map(|bound| bound.parse::<usize>());
+        match (bounds.next(), bounds.next()) {
+            (Some(Ok(first)), Some(Ok(last))) => cores.extend(first..=last),
+            (Some(Ok(core)), None) => cores.push(core),
+            _ => log::warn!("invalid range '{}' in the online CPU list", range),
+        }
+    }
+
+    cores
+}
+
+/// The online cores which have a cpufreq directory. Writing to the cpufreq files of other
+/// cores would fail.
+pub fn cpufreq_cores() -> Vec<usize> {
+    online_cpus().into_iter().filter(|&core| cpufreq_path(core).exists()).collect()
+}
+
+/// The name of the cpufreq driver managing the given core, such as `amd_pstate`.
+pub fn scaling_driver(core: usize) -> io::Result<String> { read_value(core, "scaling_driver") }
+
+/// Whether frequency boosting is enabled on all cores, or `None` if the driver does not provide
+/// a global boost control.
+pub fn get_boost() -> io::Result<Option<bool>> {
+    let path = Path::new(BOOST_PATH);
+    if !path.exists() {
+        return Ok(None);
+    }
+
+    fs::read_to_string(path).map(|value| Some(value.trim() == "1"))
+}
+
+/// Enables or disables frequency boosting on all cores. Returns `false` if the driver does not
+/// provide a global boost control, which is the case for intel_pstate.
+pub fn set_boost(enabled: bool) -> io::Result<bool> {
+    let path = Path::new(BOOST_PATH);
+    if !path.exists() {
+        return Ok(false);
+    }
+
+    fs::write(path, if enabled { "1" } else { "0" }).map(|_| true)
+}
+
+/// The governor currently used by the given core.
+pub fn get_governor(core: usize) -> io::Result<String> { read_value(core, "scaling_governor") }
+
+/// The governors which the cpufreq driver supports on the given core.
+pub fn available_governors(core: usize) -> io::Result<Vec<String>> {
+    read_value(core, "scaling_available_governors")
+        .map(|governors| governors.split_whitespace().map(String::from).collect())
+}
+
+/// Sets the governor of the given core, after validating it against the governors that the
+/// driver makes available.
+pub fn set_governor(core: usize, governor: &str) -> io::Result<()> {
+    if !available_governors(core)?.iter().any(|g| g == governor) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("governor {} is not available", governor),
+        ));
+    }
+
+    write_value(core, "scaling_governor", governor)
+}
+
+/// Applies a distinct governor to each core in the mapping. Cores which reject their governor
+/// are skipped.
+pub fn set_governors(mapping: &[(usize, &str)]) {
+    for &(core, governor) in mapping {
+        if let Err(why) = set_governor(core, governor) {
+            log::warn!("cpu{}: failed to set governor to {}: {}", core, governor, why);
+        }
+    }
+}
+
+pub fn get_energy_performance_preference(core: usize) -> io::Result<String> {
+    read_value(core, "energy_performance_preference")
+}
+
+/// Sets the energy performance preference (EPP) of the given core, after validating it against
+/// the preferences that the driver makes available.
+pub fn set_energy_performance_preference(core: usize, pref: &str) -> io::Result<()> {
+    let available = read_value(core, "energy_performance_available_preferences")?;
+    if !available.split_whitespace().any(|p| p == pref) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("energy performance preference {} is not available", pref),
+        ));
+    }
+
+    write_value(core, "energy_performance_preference", pref)
+}
+
+fn energy_perf_bias_path(core: usize) -> PathBuf {
+    PathBuf::from(format!("/sys/devices/system/cpu/cpu{}/power/energy_perf_bias", core))
+}
+
+/// The energy performance bias (EPB) of the given core, from 0 for the highest performance to
+/// 15 for the most energy savings.
+pub fn get_energy_perf_bias(core: usize) -> io::Result<u8> {
+    fs::read_to_string(energy_perf_bias_path(core))?
+        .trim()
+        .parse::<u8>()
+        .map_err(|why| io::Error::new(io::ErrorKind::InvalidData, why))
+}
+
+/// Sets the energy performance bias (EPB) of the given core. Values above 15 are rejected.
+pub fn set_energy_perf_bias(core: usize, bias: u8) -> io::Result<()> {
+    if bias > 15 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("energy performance bias {} is not between 0 and 15", bias),
+        ));
+    }
+
+    fs::write(energy_perf_bias_path(core), bias.to_string())
+}
+
+/// The current frequency of the given core, in kHz.
+pub fn current_frequency(core: usize) -> io::Result<usize> {
+    read_frequency(core, "scaling_cur_freq")
+}
+
+/// The minimum and maximum frequencies supported by the hardware, in kHz.
+pub fn frequency_limits(core: usize) -> io::Result<(usize, usize)> {
+    Ok((read_frequency(core, "cpuinfo_min_freq")?, read_frequency(core, "cpuinfo_max_freq")?))
+}
+
+/// Sets the minimum frequency of the given core, in kHz, clamped to the hardware limits.
+pub fn set_frequency_minimum(core: usize, frequency: usize) -> io::Result<()> {
+    set_frequency_limit(core, "scaling_min_freq", frequency)
+}
+
+/// Sets the maximum frequency of the given core, in kHz, clamped to the hardware limits.
+pub fn set_frequency_maximum(core: usize, frequency: usize) -> io::Result<()> {
+    set_frequency_limit(core, "scaling_max_freq", frequency)
+}
+
+/// Writes a frequency limit, and reads it back. The kernel may silently apply a different
+/// value, such as a maximum below the current minimum, which is logged rather than treated as
+/// an error.
+fn set_frequency_limit(core: usize, file: &str, frequency: usize) -> io::Result<()> {
+    let (min, max) = frequency_limits(core)?;
+    let frequency = cmp::min(cmp::max(frequency, min), max);
+    write_value(core, file, &frequency.to_string())?;
+
+    let applied = read_frequency(core, file)?;
+    if applied != frequency {
+        log::warn!(
+            "cpu{}: requested {} of {} kHz, but {} kHz was applied",
+            core,
+            file,
+            frequency,
+            applied
+        );
+    }
+
+    Ok(())
+}
+
+/// Locks the given core to an exact frequency, in kHz, by switching it to the `userspace`
+/// governor. The frequency must be in the table of available frequencies where the driver
+/// provides one, and within the hardware limits otherwise.
+pub fn set_userspace_frequency(core: usize, frequency: usize) -> io::Result<()> {
+    if !available_governors(core)?.iter().any(|g| g == "userspace") {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("cpu{}: the userspace governor is not available from this driver", core),
+        ));
+    }
+
+    let valid = match read_value(core, "scaling_available_frequencies") {
+        Ok(available) => {
+            available.split_whitespace().any(|f| f.parse::<usize>().ok() == Some(frequency))
+        }
+        Err(ref why) if why.kind() == io::ErrorKind::NotFound => {
+            let (min, max) = frequency_limits(core)?;
+            frequency >= min && frequency <= max
+        }
+        Err(why) => return Err(why),
+    };
+
+    if !valid {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("cpu{}: {} kHz is not an available frequency", core, frequency),
+        ));
+    }
+
+    set_governor(core, "userspace")?;
+    write_value(core, "scaling_setspeed", &frequency.to_string())
+}
+
+/// The frequency at `percent` of the maximum frequency of the given core, which is never lower
+/// than the minimum frequency supported by the hardware.
+fn percent_frequency(core: usize, percent: u8) -> io::Result<usize> {
+    let (min, max) = frequency_limits(core)?;
+    Ok(cmp::max(max * cmp::min(percent, 100) as usize / 100, min))
+}
+
+/// Sets the minimum frequency of all cores to `percent` of their maximum frequency, like the
+/// `min_perf_pct` value of intel_pstate.
+pub fn set_min_percent(percent: u8) -> io::Result<()> {
+    for core in cpufreq_cores() {
+        set_frequency_minimum(core, percent_frequency(core, percent)?)?;
+    }
+
+    Ok(())
+}
+
+/// The minimum and maximum frequencies of the first core, as a percentage of its maximum
+/// frequency, like the `min_perf_pct` and `max_perf_pct` values of intel_pstate.
+pub fn get_percent() -> io::Result<(u8, u8)> {
+    let core = cpufreq_cores().into_iter().next().ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, "no online core has a cpufreq directory")
+    })?;
+
+    let (_, limit) = frequency_limits(core)?;
+    if limit == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "maximum frequency is 0"));
+    }
+
+    let percent = |file| read_frequency(core, file).map(|freq| (freq * 100 / limit) as u8);
+    Ok((percent("scaling_min_freq")?, percent("scaling_max_freq")?))
+}
+
+/// Sets the maximum frequency of all cores to `percent` of their maximum frequency, like the
+/// `max_perf_pct` value of intel_pstate.
+pub fn set_max_percent(percent: u8) -> io::Result<()> {
+    for core in cpufreq_cores() {
+        set_frequency_maximum(core, percent_frequency(core, percent)?)?;
+    }
+
+    Ok(())
+}
+
+/// Permits the full frequency range on all cores, with the given governor, which is usually
+/// `performance`.
+pub fn performance(governor: &str) -> io::Result<()> { full_range(governor) }
+
+/// Permits the full frequency range on all cores, with the given governor, which is usually
+/// `schedutil`, or `powersave` on drivers without it.
+pub fn balanced(governor: &str) -> io::Result<()> { full_range(governor) }
+
+fn full_range(governor: &str) -> io::Result<()> {
+    for core in cpufreq_cores() {
+        let (min, max) = frequency_limits(core)?;
+        set_frequency_minimum(core, min)?;
+        set_frequency_maximum(core, max)?;
+        set_governor(core, governor)?;
+    }
+
+    Ok(())
+}
+
+/// Limits all cores to the configured share of their maximum frequency, with the given
+/// governor, which is usually `powersave`.
+pub fn powersave(governor: &str) -> io::Result<()> {
+    powersave_with_cap(POWERSAVE_CAP.load(Ordering::SeqCst), governor)
+}
+
+/// Changes the share of the maximum frequency permitted by `powersave`.
+pub fn set_powersave_cap(percent: u8) {
+    POWERSAVE_CAP.store(cmp::min(percent, 100), Ordering::SeqCst);
+}
+
+/// Limits all cores to `percent` of their maximum frequency, with the given governor. The limit
+/// is never lower than the minimum frequency supported by the hardware.
+pub fn powersave_with_cap(percent: u8, governor: &str) -> io::Result<()> {
+    for core in cpufreq_cores() {
+        let (min, _) = frequency_limits(core)?;
+        set_frequency_minimum(core, min)?;
+        set_frequency_maximum(core, percent_frequency(core, percent)?)?;
+        set_governor(core, governor)?;
+    }
+
+    Ok(())
+}
@@ -1,21 +1,26 @@
 use dbus::{
-    arg,
+    arg::{self, RefArg, Variant},
     channel::{MatchingReceiver, Sender},
-    message::{MatchRule, Message},
-    nonblock::SyncConnection,
+    message::{MatchRule, Message, SignalArgs},
+    nonblock::{stdintf::org_freedesktop_dbus::PropertiesPropertiesChanged, SyncConnection},
+    strings::BusName,
 };
 use dbus_crossroads::{Crossroads, IfaceBuilder, MethodErr};
 use dbus_tokio::connection;
+use intel_pstate::PState;
 use std::{
+    cmp,
+    collections::HashMap,
     fmt::Debug,
     fs,
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc,
+        mpsc, Arc,
     },
     thread,
     time::Duration,
 };
+use sysfs_class::{Backlight, Brightness, Leds, SysClass};
 use tokio::{
     signal::unix::{signal, SignalKind},
     stream::StreamExt,
@@ -23,28 +28,138 @@ use tokio::{
 };
 
 use crate::{
+    backlight::{self, BacklightExt, KeyboardBacklightExt},
     charge_thresholds::{
-        get_charge_profiles, get_charge_thresholds, set_charge_thresholds, ChargeProfile,
+        get_charge_profile, get_charge_profiles, get_charge_thresholds, set_charge_profile,
+        set_charge_thresholds, ChargeProfile,
+    },
+    config::{Config, ProfilesConfig},
+    cpu, cpufreq, ddc, err_str,
+    errors::{BacklightError, ProfileError},
+    fan::{self, FanCurve, FanDaemon},
+    graphics::{
+        self, Graphics, GraphicsDeviceError, GraphicsFunction, GraphicsMode, GraphicsPowerState,
+        SwitchGuard,
     },
-    err_str,
-    errors::ProfileError,
-    fan::FanDaemon,
-    graphics::Graphics,
     hid_backlight,
     hotplug::HotPlugDetect,
     kernel_parameters::{KernelParameter, NmiWatchdog},
+    lid,
+    model::{self, Model},
     mux::DisplayPortMux,
-    polkit, Power, DBUS_IFACE, DBUS_NAME, DBUS_PATH,
+    platform_profile, polkit, power_supply,
+    thermal::{self, ThrottleMonitor},
+    Power, DBUS_IFACE, DBUS_NAME, DBUS_PATH,
 };
 
+mod auto_brightness;
+mod backlight_watch;
+mod keyboard_timeout;
+mod profile_hook;
 mod profiles;
+mod sleep;
 
-use self::profiles::*;
+use self::{
+    auto_brightness::AutoBrightness, backlight_watch::BacklightWatch,
+    keyboard_timeout::KeyboardTimeout, profiles::*, sleep::BacklightSnapshot,
+};
 
 const THRESHOLD_POLICY: &str = "com.system76.powerdaemon.set-charge-thresholds";
 
+/// The duration of the fade to the backlight brightness configured for a profile.
+const PROFILE_FADE_MS: u64 = 500;
+
+/// The longest fade accepted by `SetDisplayBrightness`, to which longer fades are shortened.
+const MAX_FADE_MS: u64 = 2000;
+
+/// The name of the profile applied by `SetCustomProfile`.
+const CUSTOM_PROFILE: &str = "custom";
+
 static CONTINUE: AtomicBool = AtomicBool::new(true);
 
+/// Checks with polkit whether the sender of a message may change the charge thresholds.
+async fn check_threshold_authorization(
+    c: &SyncConnection,
+    sender: BusName<'static>,
+) -> Result<(), String> {
+    let pid = polkit::get_connection_unix_process_id(c, sender).await.map_err(err_str)?;
+    let permitted = if pid == 0 {
+        true
+    } else {
+        polkit::check_authorization(c, pid, 0, THRESHOLD_POLICY).await.map_err(err_str)?
+    };
+
+    if permitted {
+        Ok(())
+    } else {
+        Err("Operation not permitted by Polkit".to_string())
+    }
+}
+
+const BUSY_ERROR: &str = "Busy: a graphics switch is in progress";
+
+/// Fails with `Busy` while a graphics switch is in progress. Otherwise, the returned guard keeps
+/// graphics switches from starting until it is dropped.
+fn begin_profile_change() -> Result<SwitchGuard, String> {
+    SwitchGuard::try_acquire().ok_or_else(|| BUSY_ERROR.into())
+}
+
+/// Switches the graphics vendor, and tells clients that a reboot is required. With `force`,
+/// the configuration of other graphics switching tools is ignored. The devices are enumerated
+/// for the switch, so that it can run on a worker thread, without the daemon state.
+fn switch_graphics(
+    c: &SyncConnection,
+    vendor: &str,
+    force: bool,
+) -> Result<(), GraphicsDeviceError> {
+    let graphics = Graphics::new_without_rescan().map_err(GraphicsDeviceError::SysFs)?;
+    graphics.set_vendor(vendor, false, force)?;
+
+    // The modprobe configuration is only read at boot, so a reboot is required.
+    let message =
+        Message::new_signal(DBUS_PATH, DBUS_NAME, "GraphicsSwitchComplete").unwrap().append1(true);
+
+    if let Err(()) = c.send(message) {
+        log::error!("failed to send graphics switch complete message");
+    }
+
+    // The loaded modules only change after the reboot, so the switched to mode is reported,
+    // under the name which `GetGraphics` uses for it.
+    if let Ok(mode) = vendor.parse::<GraphicsMode>() {
+        properties_changed(c, "Graphics", Box::new(mode.to_string()));
+    }
+
+    Ok(())
+}
+
+/// Runs a graphics switch on a worker thread, and returns a receiver for its result.
+fn spawn_switch<F>(switch: F) -> mpsc::Receiver<Result<(), GraphicsDeviceError>>
+where
+    F: FnOnce() -> Result<(), GraphicsDeviceError> + Send + 'static,
+{
+    let (tx, rx) = mpsc::channel();
+    thread::spawn(move || {
+        let _ = tx.send(switch());
+    });
+    rx
+}
+
+/// Emits `PropertiesChanged` for a property of the daemon interface.
+fn properties_changed(c: &SyncConnection, name: &str, value: Box<dyn RefArg>) {
+    let mut changed_properties = HashMap::new();
+    changed_properties.insert(name.to_owned(), Variant(value));
+
+    let signal = PropertiesPropertiesChanged {
+        interface_name: DBUS_IFACE.to_owned(),
+        changed_properties,
+        invalidated_properties: Vec::new(),
+    };
+
+    if let Err(()) = c.send(signal.to_emit_message(&DBUS_PATH.into())) {
+        log::error!("failed to send properties changed message for {}", name);
+    }
+}
+
 fn signal_handling() {
     let int = signal(SignalKind::interrupt()).unwrap().map(|_| "SIGINT");
     let hup = signal(SignalKind::hangup()).unwrap().map(|_| "SIGHUP");
@@ -67,47 +182,287 @@ static PCI_RUNTIME_PM: AtomicBool = AtomicBool::new(false);
 fn pci_runtime_pm_support() -> bool { PCI_RUNTIME_PM.load(Ordering::SeqCst) }
 
 struct PowerDaemon {
-    initial_set:     bool,
-    graphics:        Graphics,
-    power_profile:   String,
-    profile_errors:  Vec<ProfileError>,
-    dbus_connection: Arc<SyncConnection>,
+    initial_set:       bool,
+    graphics:          Graphics,
+    power_profile:     String,
+    // The last profile applied on each power source, and restored when switching back to it
+    ac_profile:        String,
+    battery_profile:   String,
+    ac_online:         Option<bool>,
+    auto_switch:       bool,
+    profile_errors:    Vec<ProfileError>,
+    throttle:          ThrottleMonitor,
+    sleep_snapshot:    Option<BacklightSnapshot>,
+    backlight_watch:   BacklightWatch,
+    // The profile switched to before suspending, and the profile to restore after resuming
+    sleep_profile:     String,
+    pre_sleep_profile: Option<String>,
+    kbd_timeout:       KeyboardTimeout,
+    auto_brightness:   AutoBrightness,
+    profile_config:    ProfilesConfig,
+    // Whether the lid is closed, and the profile to restore when it opens
+    lid_closed:        Option<bool>,
+    lid_battery:       bool,
+    pre_lid_profile:   Option<String>,
+    // The CPU parameters of the last custom profile, reapplied when switching back to it
+    custom_profile:    Option<(u8, u8, bool, String)>,
+    // The script run after each successful profile switch
+    profile_hook:      Option<String>,
+    // Whether the battery is below the configured threshold, along with the keyboard
+    // brightness and profile to restore once it is no longer low
+    low_battery:       bool,
+    low_threshold:     Option<u8>,
+    low_profile:       bool,
+    pre_low_keyboards: Option<Vec<(String, u64)>>,
+    pre_low_profile:   Option<String>,
+    // The profile of an automatic switch which a graphics switch held back, retried each step
+    pending_profile:   Option<String>,
+    fan_curves:        mpsc::Sender<FanCurve>,
+    dbus_connection:   Arc<SyncConnection>,
 }
 
 impl PowerDaemon {
-    fn new(dbus_connection: Arc<SyncConnection>) -> Result<PowerDaemon, String> {
-        let graphics = Graphics::new().map_err(err_str)?;
+    fn new(
+        fan_curves: mpsc::Sender<FanCurve>,
+        dbus_connection: Arc<SyncConnection>,
+    ) -> Result<PowerDaemon, String> {
+        // Status queries use this enumeration, so the bus is only rescanned at startup, and when
+        // powering on the graphics. Some systems reset devices on a rescan, so the startup
+        // rescan may be disabled.
+        let rescan = std::env::var("S76_POWER_PCI_RESCAN").ok().map_or(true, |v| v != "0");
+        let graphics = if rescan { Graphics::new() } else { Graphics::new_without_rescan() };
+        let graphics = graphics.map_err(err_str)?;
         Ok(PowerDaemon {
             initial_set: false,
             graphics,
             power_profile: String::new(),
+            ac_profile: "Balanced".into(),
+            battery_profile: "Battery".into(),
+            ac_online: power_supply::ac_online(),
+            auto_switch: false,
             profile_errors: Vec::new(),
+            throttle: ThrottleMonitor::default(),
+            sleep_snapshot: None,
+            backlight_watch: BacklightWatch::new(),
+            sleep_profile: Config::default().sleep_profile,
+            pre_sleep_profile: None,
+            kbd_timeout: KeyboardTimeout::new(),
+            auto_brightness: AutoBrightness::new(Config::default().auto_brightness_curve),
+            profile_config: ProfilesConfig::default(),
+            lid_closed: lid::closed(),
+            lid_battery: false,
+            pre_lid_profile: None,
+            custom_profile: None,
+            profile_hook: None,
+            low_battery: false,
+            low_threshold: None,
+            low_profile: false,
+            pre_low_keyboards: None,
+            pre_low_profile: None,
+            pending_profile: None,
+            fan_curves,
             dbus_connection,
         })
     }
 
+    /// Records the backlight brightness and switches to the sleep profile before suspending,
+    /// and restores both after resuming.
+    fn prepare_for_sleep(&mut self, suspending: bool) {
+        if suspending {
+            log::info!("Saving backlight brightness before suspend");
+            self.sleep_snapshot = Some(BacklightSnapshot::new());
+
+            let sleep_profile = self.sleep_profile.clone();
+            if sleep_profile != "none" && !sleep_profile.eq_ignore_ascii_case(&self.power_profile) {
+                log::info!("Switching to the {} profile before suspend", sleep_profile);
+                self.pre_sleep_profile = Some(self.power_profile.clone());
+                if let Err(why) = self.set_profile_by_name(&sleep_profile) {
+                    log::warn!("failed to switch profile before suspend: {}", why);
+                }
+            }
+        } else {
+            if let Some(profile) = self.pre_sleep_profile.take() {
+                log::info!("Restoring the {} profile after resume", profile);
+                self.switch_profile_automatically(&profile);
+            }
+
+            if let Some(snapshot) = self.sleep_snapshot.take() {
+                log::info!("Restoring backlight brightness after resume");
+                snapshot.restore();
+            }
+        }
+    }
+
+    /// Applies the settings of the configuration file which take effect at runtime. The startup
+    /// profile and charge profile are only applied when the daemon starts.
+    fn apply_config(&mut self, config: &Config) {
+        cpufreq::set_powersave_cap(config.powersave_max_percent);
+        backlight::set_min_brightness_percent(config.min_brightness);
+        self.auto_switch = config.auto_profile_switch;
+        self.auto_brightness.set_curve(config.auto_brightness_curve.clone());
+        self.profile_config = config.profiles.clone();
+        self.sleep_profile = config.sleep_profile.clone();
+        self.lid_battery = config.lid_close_battery;
+        graphics::set_regenerate_initramfs(config.regenerate_initramfs);
+        self.profile_hook = config.on_profile_change.clone();
+        self.low_threshold = config.low_battery_percent;
+        self.low_profile = config.low_battery_profile;
+    }
+
+    /// Applies a profile by name, such as `battery` or `Battery`, falling back to balanced.
+    fn set_profile_by_name(&mut self, name: &str) -> Result<(), String> {
+        match name.to_lowercase().as_str() {
+            "battery" => self.battery(),
+            "performance" => self.performance(),
+            "balanced" => self.balanced(),
+            CUSTOM_PROFILE if self.custom_profile.is_some() => {
+                let (min, max, no_turbo, governor) = self.custom_profile.clone().unwrap();
+                self.set_custom_profile(min, max, no_turbo, &governor)
+            }
+            other => {
+                log::warn!("Unknown profile '{}', using balanced", other);
+                self.balanced()
+            }
+        }
+    }
+
+    /// Powers the discrete graphics on or off, or only the device with the given ID. With
+    /// `force`, drivers which are still bound are unbound rather than failing.
+    fn power_graphics(
+        &mut self,
+        id: Option<&str>,
+        power: bool,
+        force: bool,
+    ) -> Result<(), GraphicsDeviceError> {
+        if force && !power {
+            log::warn!("Forcibly powering off graphics, even if a driver is bound");
+        }
+
+        match id {
+            Some(id) => self.graphics.set_power_for(id, power, force)?,
+            None => self.graphics.set_power(power, force)?,
+        }
+
+        // Pick up any functions that the rescan brought back, without another rescan. Removed
+        // devices are kept, so that they can be found again when powering back on.
+        if power {
+            self.graphics.refresh().map_err(GraphicsDeviceError::SysFs)?;
+        }
+
+        if let Ok(power) = self.graphics.get_power() {
+            properties_changed(&self.dbus_connection, "GraphicsPower", Box::new(power));
+        }
+
+        Ok(())
+    }
+
+    /// Steps the keyboard backlight with the given index, or every keyboard backlight if the
+    /// index is negative, returning the new brightness of the first one as a percentage.
+    fn step_keyboard_brightness(&mut self, device: i32, up: bool) -> Result<u8, String> {
+        let keyboards = Leds::iter_keyboards().collect::<Result<Vec<_>, _>>().map_err(err_str)?;
+        let keyboards = if device < 0 {
+            keyboards
+        } else {
+            let keyboard = keyboards
+                .into_iter()
+                .nth(device as usize)
+                .ok_or_else(|| format!("keyboard backlight {} not found", device))?;
+            vec![keyboard]
+        };
+
+        let mut percent = None;
+        for keyboard in &keyboards {
+            let new = keyboard.step_level(up).map_err(err_str)?;
+            percent = percent.or(Some(new));
+        }
+
+        percent.ok_or_else(|| "no keyboard backlight found".to_string())
+    }
+
+    /// Fades the backlights to the brightness configured for the profile, if any.
+    fn apply_profile_backlights(&mut self, name: &str) {
+        let config = match self.profile_config.get(name) {
+            Some(config) => config,
+            None => return,
+        };
+
+        // Auto brightness would treat the change as manual, and disable itself.
+        if let Some(percent) = config.backlight.filter(|_| !self.auto_brightness.is_enabled()) {
+            for backlight in Backlight::iter().filter_map(Result::ok) {
+                let result = backlight.max_brightness().and_then(|max| {
+                    let target = max * u64::from(cmp::min(percent, 100)) / 100;
+                    backlight.set_brightness_smooth(target, PROFILE_FADE_MS)
+                });
+
+                if let Err(why) = result {
+                    let error = BacklightError::Set(backlight.id().to_owned(), why);
+                    self.profile_errors.push(error.into());
+                }
+            }
+        }
+
+        if let Some(percent) = config.keyboard_backlight {
+            for keyboard in Leds::iter_keyboards().filter_map(Result::ok) {
+                let result = keyboard.max_brightness().and_then(|max| {
+                    keyboard.set_brightness(max * u64::from(cmp::min(percent, 100)) / 100)
+                });
+
+                if let Err(why) = result {
+                    let error = BacklightError::Set(keyboard.id().to_owned(), why);
+                    self.profile_errors.push(error.into());
+                }
+            }
+        }
+    }
+
     fn apply_profile(
         &mut self,
-        func: fn(&mut Vec<ProfileError>, bool),
+        func: impl FnOnce(&mut Vec<ProfileError>, bool),
         name: &str,
     ) -> Result<(), String> {
-        if self.power_profile == name {
+        if self.ac_online == Some(false) {
+            self.battery_profile = name.into();
+        } else {
+            self.ac_profile = name.into();
+        }
+
+        // A custom profile may have been changed, so it is always applied again.
+        if self.power_profile == name && name != CUSTOM_PROFILE {
             log::info!("profile was already set");
+            self.pending_profile = None;
             return Ok(());
         }
 
-        func(&mut self.profile_errors, self.initial_set);
+        // Held until the profile is applied, so that a graphics switch cannot start meanwhile.
+        let _guard = begin_profile_change()?;
 
-        let message =
-            Message::new_signal(DBUS_PATH, DBUS_NAME, "PowerProfileSwitch").unwrap().append1(name);
+        // A profile applied since an automatic switch was held back supersedes it.
+        self.pending_profile = None;
 
-        if let Err(()) = self.dbus_connection.send(message) {
-            log::error!("failed to send power profile switch message");
+        func(&mut self.profile_errors, self.initial_set);
+        if self.initial_set {
+            self.apply_profile_backlights(name);
         }
 
+        // Some settings routinely fail on real hardware, so a partially applied profile is still
+        // the current one. Clients are only told of the switch when it fully succeeded.
         self.power_profile = name.into();
 
         if self.profile_errors.is_empty() {
+            let message = Message::new_signal(DBUS_PATH, DBUS_NAME, "PowerProfileSwitch")
+                .unwrap()
+                .append1(name);
+
+            if let Err(()) = self.dbus_connection.send(message) {
+                log::error!("failed to send power profile switch message");
+            }
+
+            properties_changed(&self.dbus_connection, "Profile", Box::new(name.to_owned()));
+
+            if let Some(ref script) = self.profile_hook {
+                profile_hook::run(script, name);
+            }
+
             Ok(())
         } else {
             let mut error_message = String::from("Errors found when setting profile:");
@@ -118,19 +473,192 @@ impl PowerDaemon {
             Err(error_message)
         }
     }
+
+    /// When the AC adapter is plugged in or unplugged, applies the profile that was last used
+    /// on the new power source, if automatic switching is enabled.
+    fn ac_step(&mut self) {
+        let online = match power_supply::ac_online() {
+            Some(online) => online,
+            None => return,
+        };
+
+        if self.ac_online.replace(online) == Some(online) || !self.auto_switch {
+            return;
+        }
+
+        let profile = if online { self.ac_profile.clone() } else { self.battery_profile.clone() };
+        log::info!(
+            "AC adapter {}, switching to {}",
+            if online { "plugged in" } else { "unplugged" },
+            profile
+        );
+        self.switch_profile_automatically(&profile);
+    }
+
+    /// Switches to a profile on behalf of one of the periodic steps. The steps only act on a
+    /// change of state, so if a graphics switch is in progress, the profile is retried by
+    /// `pending_profile_step` instead of being dropped.
+    fn switch_profile_automatically(&mut self, profile: &str) {
+        match self.set_profile_by_name(profile) {
+            Ok(()) => (),
+            Err(ref why) if why == BUSY_ERROR => {
+                log::info!("graphics switch in progress, switching to {} later", profile);
+                self.pending_profile = Some(profile.to_owned());
+            }
+            Err(why) => log::warn!("failed to switch profile: {}", why),
+        }
+    }
+
+    /// Retries the profile of an automatic switch which a graphics switch held back.
+    fn pending_profile_step(&mut self) {
+        if let Some(profile) = self.pending_profile.take() {
+            log::info!("retrying the switch to {}", profile);
+            self.switch_profile_automatically(&profile);
+        }
+    }
+
+    /// Notices display backlights which were plugged in or unplugged, and drops removed ones
+    /// from the state which tracks them.
+    fn backlight_step(&mut self) {
+        let (added, removed) = self.backlight_watch.step();
+        if added.is_empty() && removed.is_empty() {
+            return;
+        }
+
+        for id in &added {
+            log::info!("Backlight {} was added", id);
+        }
+
+        for id in &removed {
+            log::info!("Backlight {} was removed", id);
+            if let Some(ref mut snapshot) = self.sleep_snapshot {
+                snapshot.forget_backlight(id);
+            }
+        }
+
+        self.auto_brightness.backlights_changed();
+    }
+
+    /// When the lid is closed while on battery, switches to the battery profile if enabled, and
+    /// restores the previous profile when the lid is opened again. The machine may keep running
+    /// with the lid closed, so this is separate from the sleep profile.
+    fn lid_step(&mut self) {
+        let closed = match lid::closed() {
+            Some(closed) => closed,
+            None => return,
+        };
+
+        if self.lid_closed.replace(closed) == Some(closed) || !self.lid_battery {
+            return;
+        }
+
+        if closed {
+            if self.ac_online == Some(false) && self.power_profile != "Battery" {
+                log::info!("Lid closed on battery, switching to Battery");
+                self.pre_lid_profile = Some(self.power_profile.clone());
+                self.switch_profile_automatically("Battery");
+            }
+        } else if let Some(profile) = self.pre_lid_profile.take() {
+            log::info!("Lid opened, switching to {}", profile);
+            self.switch_profile_automatically(&profile);
+        }
+    }
+
+    /// When the battery falls below the configured percentage while on battery, fades the
+    /// keyboard backlight off, and switches to the battery profile if enabled. Both are
+    /// restored once the AC adapter is plugged in, or the battery charges past the threshold.
+    fn low_battery_step(&mut self) {
+        let threshold = match self.low_threshold {
+            Some(threshold) => threshold,
+            None => return,
+        };
+
+        let percent = match power_supply::battery_percent() {
+            Ok(percent) => percent,
+            Err(_) => return,
+        };
+
+        let low = self.ac_online == Some(false) && percent < threshold;
+        if low == self.low_battery {
+            return;
+        }
+
+        self.low_battery = low;
+        if low {
+            log::info!("Battery at {}%, turning off the keyboard backlight", percent);
+            self.pre_low_keyboards = Some(sleep::snapshot(Leds::iter_keyboards()));
+            for keyboard in Leds::iter_keyboards().filter_map(Result::ok) {
+                backlight::fade_in_background(keyboard, |keyboard| {
+                    keyboard.fade_to(0, PROFILE_FADE_MS)
+                });
+            }
+
+            if self.low_profile && self.power_profile != "Battery" {
+                log::info!("Battery low, switching to Battery");
+                self.pre_low_profile = Some(self.power_profile.clone());
+                self.switch_profile_automatically("Battery");
+            }
+        } else {
+            log::info!("Battery no longer low, restoring the keyboard backlight");
+            if let Some(keyboards) = self.pre_low_keyboards.take() {
+                sleep::restore::<Leds>("keyboard backlight", &keyboards);
+            }
+
+            if let Some(profile) = self.pre_low_profile.take() {
+                log::info!("Battery no longer low, switching to {}", profile);
+                self.switch_profile_automatically(&profile);
+            }
+        }
+    }
 }
 
 impl Power for PowerDaemon {
     fn battery(&mut self) -> Result<(), String> {
-        self.apply_profile(battery, "Battery").map_err(err_str)
+        let config = self.profile_config.battery.clone();
+        self.apply_profile(|errors, brightness| battery(errors, brightness, &config), "Battery")
     }
 
     fn balanced(&mut self) -> Result<(), String> {
-        self.apply_profile(balanced, "Balanced").map_err(err_str)
+        let config = self.profile_config.balanced.clone();
+        self.apply_profile(|errors, brightness| balanced(errors, brightness, &config), "Balanced")
     }
 
     fn performance(&mut self) -> Result<(), String> {
-        self.apply_profile(performance, "Performance").map_err(err_str)
+        let config = self.profile_config.performance.clone();
+        let apply =
+            |errors: &mut Vec<ProfileError>, brightness| performance(errors, brightness, &config);
+        self.apply_profile(apply, "Performance")
+    }
+
+    fn set_custom_profile(
+        &mut self,
+        min_perf_pct: u8,
+        max_perf_pct: u8,
+        no_turbo: bool,
+        governor: &str,
+    ) -> Result<(), String> {
+        if max_perf_pct > 100 {
+            return Err(format!("max_perf_pct {} is greater than 100", max_perf_pct));
+        }
+
+        if min_perf_pct > max_perf_pct {
+            return Err(format!(
+                "min_perf_pct {} is greater than max_perf_pct {}",
+                min_perf_pct, max_perf_pct
+            ));
+        }
+
+        let governors = cpufreq::available_governors(0).unwrap_or_default();
+        if !governors.is_empty() && !governors.iter().any(|g| g == governor) {
+            return Err(format!("governor {} is not available", governor));
+        }
+
+        self.custom_profile = Some((min_perf_pct, max_perf_pct, no_turbo, governor.to_owned()));
+        let apply = |errors: &mut Vec<ProfileError>, _set_brightness: bool| {
+            custom(errors, min_perf_pct, max_perf_pct, no_turbo, governor)
+        };
+
+        self.apply_profile(apply, CUSTOM_PROFILE).map_err(err_str)
     }
 
     fn get_external_displays_require_dgpu(&mut self) -> Result<bool, String> {
@@ -141,7 +669,7 @@ impl Power for PowerDaemon {
         self.graphics.get_default_graphics().map_err(err_str)
     }
 
-    fn get_graphics(&mut self) -> Result<String, String> {
+    fn get_graphics(&mut self) -> Result<GraphicsMode, String> {
         self.graphics.get_vendor().map_err(err_str)
     }
 
@@ -150,21 +678,96 @@ impl Power for PowerDaemon {
     fn get_switchable(&mut self) -> Result<bool, String> { Ok(self.graphics.can_switch()) }
 
     fn set_graphics(&mut self, vendor: &str) -> Result<(), String> {
-        self.graphics.set_vendor(vendor).map_err(err_str)
+        switch_graphics(&self.dbus_connection, vendor, false).map_err(err_str)
+    }
+
+    fn set_graphics_force(&mut self, vendor: &str) -> Result<(), String> {
+        switch_graphics(&self.dbus_connection, vendor, true).map_err(err_str)
+    }
+
+    fn set_graphics_dry_run(&mut self, vendor: &str) -> Result<Vec<String>, String> {
+        self.graphics.set_vendor(vendor, true, false).map_err(err_str)
+    }
+
+    fn get_nvidia_version(&mut self) -> Result<String, String> {
+        Ok(graphics::nvidia_driver_version())
+    }
+
+    fn get_nvidia_fallback_enabled(&mut self) -> Result<String, String> {
+        Ok(graphics::nvidia_fallback_state())
+    }
+
+    fn get_nvidia_temperature(&mut self) -> Result<i32, String> {
+        self.graphics.nvidia_temperature().map_err(err_str)
     }
 
     fn get_graphics_power(&mut self) -> Result<bool, String> {
         self.graphics.get_power().map_err(err_str)
     }
 
+    fn get_graphics_power_state(&mut self) -> Result<GraphicsPowerState, String> {
+        self.graphics.get_power_state().map_err(err_str)
+    }
+
     fn set_graphics_power(&mut self, power: bool) -> Result<(), String> {
-        self.graphics.set_power(power).map_err(err_str)
+        self.power_graphics(None, power, false).map_err(err_str)
+    }
+
+    fn set_graphics_power_force(&mut self, power: bool) -> Result<(), String> {
+        self.power_graphics(None, power, true).map_err(err_str)
+    }
+
+    fn set_graphics_power_for(&mut self, id: &str, power: bool) -> Result<(), String> {
+        self.power_graphics(Some(id), power, false).map_err(err_str)
+    }
+
+    fn get_pstate(&mut self) -> Result<(u8, u8, bool), String> {
+        let values = PState::new().and_then(|pstate| pstate.values()).map_err(err_str)?;
+        Ok((values.min_perf_pct, values.max_perf_pct, values.no_turbo))
+    }
+
+    fn get_boot_vga(&mut self) -> Result<String, String> {
+        self.graphics.boot_vga().map(String::from).ok_or_else(|| "no boot VGA device found".into())
+    }
+
+    fn get_integrated_vendor(&mut self) -> Result<String, String> {
+        self.graphics
+            .integrated_vendor()
+            .map(String::from)
+            .ok_or_else(|| "no integrated graphics found".into())
+    }
+
+    fn get_graphics_power_blockers(&mut self) -> Result<Vec<(String, String, Vec<u32>)>, String> {
+        self.graphics.power_blockers().map_err(err_str)
+    }
+
+    fn list_graphics_functions(&mut self) -> Result<Vec<GraphicsFunction>, String> {
+        Ok(self.graphics.list_functions())
+    }
+
+    fn list_graphics_devices(&mut self) -> Result<Vec<(String, String, String)>, String> {
+        Ok(self
+            .graphics
+            .devices()
+            .into_iter()
+            .map(|(dev, vendor)| {
+                (dev.id().to_owned(), vendor.to_owned(), dev.power_state().to_string())
+            })
+            .collect())
     }
 
     fn auto_graphics_power(&mut self) -> Result<(), String> {
         self.graphics.auto_power().map_err(err_str)
     }
 
+    fn set_graphics_runtime_pm(&mut self, enabled: bool) -> Result<(), String> {
+        self.graphics.set_runtime_pm(enabled).map_err(err_str)
+    }
+
+    fn set_graphics_d3cold(&mut self, allowed: bool) -> Result<(), String> {
+        self.graphics.set_d3cold(allowed).map_err(err_str)
+    }
+
     fn get_charge_thresholds(&mut self) -> Result<(u8, u8), String> { get_charge_thresholds() }
 
     fn set_charge_thresholds(&mut self, thresholds: (u8, u8)) -> Result<(), String> {
@@ -175,6 +778,204 @@ impl Power for PowerDaemon {
     fn get_charge_profiles(&mut self) -> Result<Vec<ChargeProfile>, String> {
         Ok(get_charge_profiles())
     }
+
+    fn get_charge_profile(&mut self) -> Result<String, String> { get_charge_profile() }
+
+    fn set_charge_profile(&mut self, profile: &str) -> Result<(), String> {
+        // NOTE: This method is not actually called by daemon
+        set_charge_profile(profile)
+    }
+
+    fn get_cpu_frequencies(&mut self) -> Result<Vec<u32>, String> {
+        // Offline cores have no cpufreq directory, and are reported as 0
+        let cpus = cpufreq::num_cpus().unwrap_or(0);
+        Ok((0..cpus)
+            .map(|core| cpufreq::current_frequency(core).map_or(0, |freq| freq as u32))
+            .collect())
+    }
+
+    fn get_thermal_state(&mut self) -> Result<(i32, bool), String> {
+        let temp = thermal::max_temperature().unwrap_or(0);
+        Ok((temp, self.throttle.is_throttling()))
+    }
+
+    fn get_temperatures(&mut self) -> Result<HashMap<String, i32>, String> {
+        Ok(thermal::temperatures())
+    }
+
+    fn get_cpu_temperature(&mut self) -> Result<i32, String> {
+        thermal::cpu_temperature().ok_or_else(|| "no CPU temperature sensor found".into())
+    }
+
+    fn get_model(&mut self) -> Result<String, String> { Ok(Model::detect().to_string()) }
+
+    fn get_firmware_versions(&mut self) -> Result<(String, String), String> {
+        Ok(model::firmware_versions())
+    }
+
+    fn set_display_brightness(&mut self, percent: u8, fade_ms: u32) -> Result<(), String> {
+        self.auto_brightness.disable();
+        let backlight = backlight::primary().map_err(err_str)?;
+        let max = backlight.max_brightness().map_err(err_str)?;
+        let target = max * u64::from(cmp::min(percent, 100)) / 100;
+        let fade_ms = cmp::min(u64::from(fade_ms), MAX_FADE_MS);
+        backlight::fade_in_background(backlight, move |backlight| {
+            backlight.set_brightness_smooth(target, fade_ms)
+        });
+        Ok(())
+    }
+
+    fn increase_display_brightness(&mut self, step: u8) -> Result<u8, String> {
+        self.auto_brightness.disable();
+        backlight::primary().and_then(|b| b.step_brightness(i16::from(step))).map_err(err_str)
+    }
+
+    fn decrease_display_brightness(&mut self, step: u8) -> Result<u8, String> {
+        self.auto_brightness.disable();
+        backlight::primary().and_then(|b| b.step_brightness(-i16::from(step))).map_err(err_str)
+    }
+
+    fn increase_keyboard_brightness(&mut self, device: i32) -> Result<u8, String> {
+        self.step_keyboard_brightness(device, true)
+    }
+
+    fn decrease_keyboard_brightness(&mut self, device: i32) -> Result<u8, String> {
+        self.step_keyboard_brightness(device, false)
+    }
+
+    fn set_external_brightness(&mut self, display: u32, percent: u8) -> Result<(), String> {
+        ddc::set_brightness(display as usize, percent)
+    }
+
+    fn set_keyboard_color(&mut self, zone: &str, color: &str) -> Result<(), String> {
+        let rgb = backlight::parse_color(color)?;
+        let keyboard = Leds::iter_keyboards()
+            .filter_map(Result::ok)
+            .find(|keyboard| keyboard.has_color_zone(zone))
+            .ok_or_else(|| format!("no keyboard backlight supports color zone '{}'", zone))?;
+        keyboard.set_color(zone, rgb).map_err(err_str)
+    }
+
+    fn get_fan_speeds(&mut self) -> Result<Vec<u32>, String> {
+        fan::get_fan_rpms().map_err(err_str)
+    }
+
+    fn set_fan_duty(&mut self, index: u32, percent: u8) -> Result<(), String> {
+        fan::set_fan_duty(index as usize, percent).map_err(err_str)
+    }
+
+    fn set_fan_curve(&mut self, curve: &str) -> Result<(), String> {
+        let curve = curve.parse::<FanCurve>()?;
+        self.fan_curves.send(curve).map_err(|_| "fan daemon is not running".to_string())
+    }
+
+    fn set_auto_profile_switch(&mut self, enabled: bool) -> Result<(), String> {
+        self.auto_switch = enabled;
+        Ok(())
+    }
+
+    fn reload_config(&mut self) -> Result<(), String> {
+        let config = Config::try_load()?;
+        log::info!("Reloading the configuration");
+        self.apply_config(&config);
+        Ok(())
+    }
+
+    fn get_turbo(&mut self) -> Result<bool, String> { cpu::get_turbo().map_err(err_str) }
+
+    fn set_turbo(&mut self, enabled: bool) -> Result<(), String> {
+        cpu::set_turbo(enabled).map_err(err_str)
+    }
+
+    fn get_smt(&mut self) -> Result<bool, String> { cpu::get_smt().map_err(err_str) }
+
+    fn set_smt(&mut self, enabled: bool) -> Result<(), String> {
+        cpu::set_smt(enabled).map_err(err_str)
+    }
+
+    fn set_auto_brightness(&mut self, enabled: bool) -> Result<(), String> {
+        self.auto_brightness.set_enabled(enabled).map_err(err_str)
+    }
+
+    fn set_keyboard_timeout(&mut self, seconds: u32) -> Result<(), String> {
+        self.kbd_timeout.set_timeout(seconds).map_err(err_str)
+    }
+
+    fn set_online_cores(&mut self, count: u32) -> Result<(), String> {
+        cpu::set_online_cores(count as usize).map_err(err_str)
+    }
+
+    fn get_platform_profile(&mut self) -> Result<String, String> {
+        if !platform_profile::is_supported() {
+            return Err("ACPI platform profile is not supported on this system".into());
+        }
+
+        platform_profile::get().map_err(err_str)
+    }
+
+    fn get_battery_health(&mut self) -> Result<(u8, u32), String> {
+        power_supply::battery_health().map_err(err_str)
+    }
+
+    fn get_power_draw(&mut self) -> Result<f64, String> {
+        power_supply::power_draw_watts().map_err(err_str)
+    }
+
+    fn get_battery_status(&mut self) -> Result<(String, u64), String> {
+        power_supply::battery_status().map_err(err_str)
+    }
+
+    fn get_available_governors(&mut self) -> Result<Vec<String>, String> {
+        cpufreq::available_governors(0).map_err(err_str)
+    }
+
+    fn get_governors(&mut self) -> Result<Vec<String>, String> {
+        // Offline cores have no cpufreq directory, and are reported as an empty string
+        let cpus = cpufreq::num_cpus().unwrap_or(0);
+        Ok((0..cpus).map(|core| cpufreq::get_governor(core).unwrap_or_default()).collect())
+    }
+
+    fn set_governor(&mut self, governor: &str) -> Result<(), String> {
+        cpu::set_governor(governor).map_err(err_str)
+    }
+
+    fn set_core_governor(&mut self, core: u32, governor: &str) -> Result<(), String> {
+        let cpus = cpufreq::num_cpus().unwrap_or(0);
+        if core as usize >= cpus {
+            return Err(format!("core {} is out of range, as there are {} cores", core, cpus));
+        }
+
+        cpufreq::set_governor(core as usize, governor).map_err(err_str)
+    }
+
+    fn set_cpu_frequency(&mut self, core: u32, frequency: u32) -> Result<(), String> {
+        let cpus = cpufreq::num_cpus().unwrap_or(0);
+        if core as usize >= cpus {
+            return Err(format!("core {} is out of range, as there are {} cores", core, cpus));
+        }
+
+        cpufreq::set_userspace_frequency(core as usize, frequency as usize).map_err(err_str)
+    }
+
+    fn get_perf_percent(&mut self) -> Result<(u8, u8), String> {
+        cpu::get_perf_percent().map_err(err_str)
+    }
+
+    fn set_min_perf_percent(&mut self, percent: u8) -> Result<(), String> {
+        if percent > 100 {
+            return Err(format!("percent {} is greater than 100", percent));
+        }
+
+        cpu::set_min_perf_percent(percent).map_err(err_str)
+    }
+
+    fn set_max_perf_percent(&mut self, percent: u8) -> Result<(), String> {
+        if percent > 100 {
+            return Err(format!("percent {} is greater than 100", percent));
+        }
+
+        cpu::set_max_perf_percent(percent).map_err(err_str)
+    }
 }
 
 #[tokio::main]
@@ -196,7 +997,8 @@ pub async fn daemon() -> Result<(), String> {
         panic!("Lost connection to D-Bus: {}", err);
     });
 
-    let mut daemon = PowerDaemon::new(c.clone())?;
+    let (fan_curve_tx, fan_curve_rx) = mpsc::channel();
+    let mut daemon = PowerDaemon::new(fan_curve_tx, c.clone())?;
     let nvidia_exists = !daemon.graphics.nvidia.is_empty();
 
     log::info!("Disabling NMI Watchdog (for kernel debugging only)");
@@ -217,12 +1019,22 @@ pub async fn daemon() -> Result<(), String> {
         }
     }
 
-    log::info!("Initializing with the balanced profile");
-    if let Err(why) = daemon.balanced() {
+    let config = Config::load();
+    daemon.apply_config(&config);
+
+    log::info!("Initializing with the {} profile", config.default_profile);
+    if let Err(why) = daemon.set_profile_by_name(&config.default_profile) {
         log::warn!("Failed to set initial profile: {}", why);
     }
     daemon.initial_set = true;
 
+    if let Some(ref profile) = config.charge_profile {
+        log::info!("Setting the {} charge profile", profile);
+        if let Err(why) = set_charge_profile(profile) {
+            log::warn!("Failed to set charge profile: {}", why);
+        }
+    }
+
     log::info!("Registering dbus name {}", DBUS_NAME);
     c.request_name(DBUS_NAME, false, true, false).await.map_err(err_str)?;
 
@@ -234,23 +1046,83 @@ pub async fn daemon() -> Result<(), String> {
             tokio::spawn(x);
         }),
     )));
+    // Methods are handled one at a time under the Crossroads lock, except for the graphics
+    // switches, `SetGraphics` and `SetGraphicsForce`, which run on a worker thread. A switch and
+    // a profile change are mutually exclusive through the `SwitchGuard`: while a switch runs,
+    // `Performance`, `Balanced`, `Battery`, `SetCustomProfile`, and the automatic profile
+    // changes fail with `Busy`, and a switch requested during a profile change, or during
+    // another switch, fails with `Busy`.
     let iface_token = cr.register(DBUS_IFACE, |b| {
         sync_action_method(b, "Performance", PowerDaemon::performance);
         sync_action_method(b, "Balanced", PowerDaemon::balanced);
         sync_action_method(b, "Battery", PowerDaemon::battery);
+        sync_method(
+            b,
+            "SetCustomProfile",
+            ("min_perf_pct", "max_perf_pct", "no_turbo", "governor"),
+            (),
+            |d, (min, max, no_turbo, governor): (u8, u8, bool, String)| {
+                d.set_custom_profile(min, max, no_turbo, &governor)
+            },
+        );
+        sync_get_method(b, "GetExternalDisplaysRequireDGPU", "required", |d| {
+            d.graphics.get_external_displays_require_dgpu()
+        });
+        sync_get_method(b, "GetDefaultGraphics", "vendor", |d| d.graphics.get_default_graphics());
+        sync_get_method(b, "GetGraphics", "vendor", |d| {
+            d.graphics.get_vendor().map(|m| m.to_string())
+        });
+        switch_graphics_method(b, "SetGraphics", false, c.clone());
+        switch_graphics_method(b, "SetGraphicsForce", true, c.clone());
+        sync_method(b, "SetGraphicsDryRun", ("vendor",), ("actions",), |d, (s,): (String,)| {
+            d.graphics.set_vendor(&s, true, false).map(|actions| (actions,))
+        });
+        sync_get_method(b, "GetNvidiaVersion", "version", PowerDaemon::get_nvidia_version);
         sync_get_method(
             b,
-            "GetExternalDisplaysRequireDGPU",
-            "required",
-            PowerDaemon::get_external_displays_require_dgpu,
+            "GetNvidiaFallbackEnabled",
+            "state",
+            PowerDaemon::get_nvidia_fallback_enabled,
         );
-        sync_get_method(b, "GetDefaultGraphics", "vendor", PowerDaemon::get_default_graphics);
-        sync_get_method(b, "GetGraphics", "vendor", PowerDaemon::get_graphics);
-        sync_set_method(b, "SetGraphics", "vendor", |d, s: String| d.set_graphics(&s));
+        sync_get_method(b, "GetNvidiaTemperature", "temperature", |d| {
+            d.graphics.nvidia_temperature()
+        });
         sync_get_method(b, "GetProfile", "profile", PowerDaemon::get_profile);
         sync_get_method(b, "GetSwitchable", "switchable", PowerDaemon::get_switchable);
-        sync_get_method(b, "GetGraphicsPower", "power", PowerDaemon::get_graphics_power);
-        sync_set_method(b, "SetGraphicsPower", "power", PowerDaemon::set_graphics_power);
+        sync_get_method(b, "GetGraphicsPower", "power", |d| d.graphics.get_power());
+        sync_get_method(b, "GetGraphicsPowerState", "state", |d| {
+            d.graphics.get_power_state().map(|s| s.to_string())
+        });
+        sync_set_method(b, "SetGraphicsPower", "power", |d, power| {
+            d.power_graphics(None, power, false)
+        });
+        sync_set_method(b, "SetGraphicsPowerForce", "power", |d, power| {
+            d.power_graphics(None, power, true)
+        });
+        sync_method(
+            b,
+            "SetGraphicsPowerFor",
+            ("id", "power"),
+            (),
+            |d, (id, power): (String, bool)| d.power_graphics(Some(&id), power, false),
+        );
+        sync_get_method(b, "GetBootVga", "vendor", PowerDaemon::get_boot_vga);
+        sync_get_method(b, "GetIntegratedVendor", "vendor", PowerDaemon::get_integrated_vendor);
+        sync_get_method(b, "GetPState", "pstate", PowerDaemon::get_pstate);
+        sync_get_method(
+            b,
+            "ListGraphicsFunctions",
+            "functions",
+            PowerDaemon::list_graphics_functions,
+        );
+        sync_get_method(b, "ListGraphicsDevices", "devices", PowerDaemon::list_graphics_devices);
+        sync_get_method(b, "GetGraphicsPowerBlockers", "blockers", |d| d.graphics.power_blockers());
+        sync_set_method(b, "SetGraphicsRuntimePm", "enabled", |d, enabled| {
+            d.graphics.set_runtime_pm(enabled)
+        });
+        sync_set_method(b, "SetGraphicsD3Cold", "allowed", |d, allowed| {
+            d.graphics.set_d3cold(allowed)
+        });
         sync_get_method(b, "GetChargeThresholds", "thresholds", PowerDaemon::get_charge_thresholds);
         let c_clone = c.clone();
         b.method_with_cr_async(
@@ -261,33 +1133,155 @@ pub async fn daemon() -> Result<(), String> {
                 let sender = ctx.message().sender().unwrap().into_static();
                 let c = c_clone.clone();
                 let res = async move {
-                    let pid = polkit::get_connection_unix_process_id(&c, sender)
-                        .await
-                        .map_err(err_str)?;
-                    let permitted = if pid == 0 {
-                        true
-                    } else {
-                        polkit::check_authorization(&c, pid, 0, THRESHOLD_POLICY)
-                            .await
-                            .map_err(err_str)?
-                    };
-                    if permitted {
-                        set_charge_thresholds(thresholds)?;
-                        Ok(())
-                    } else {
-                        Err("Operation not permitted by Polkit".to_string())
+                    check_threshold_authorization(&c, sender).await?;
+                    set_charge_thresholds(thresholds)?;
+                    if let Ok(thresholds) = get_charge_thresholds() {
+                        properties_changed(&c, "ChargeThresholds", Box::new(thresholds));
                     }
+                    Ok::<(), String>(())
                 };
                 async move { ctx.reply(res.await.map_err(|e| MethodErr::failed(&e))) }
             },
         );
         sync_get_method(b, "GetChargeProfiles", "profiles", PowerDaemon::get_charge_profiles);
+        sync_get_method(b, "GetChargeProfile", "profile", PowerDaemon::get_charge_profile);
+        let c_clone = c.clone();
+        b.method_with_cr_async(
+            "SetChargeProfile",
+            ("profile",),
+            (),
+            move |mut ctx, _cr, (profile,): (String,)| {
+                let sender = ctx.message().sender().unwrap().into_static();
+                let c = c_clone.clone();
+                let res = async move {
+                    check_threshold_authorization(&c, sender).await?;
+                    set_charge_profile(&profile)?;
+                    if let Ok(thresholds) = get_charge_thresholds() {
+                        properties_changed(&c, "ChargeThresholds", Box::new(thresholds));
+                    }
+                    Ok::<(), String>(())
+                };
+                async move { ctx.reply(res.await.map_err(|e| MethodErr::failed(&e))) }
+            },
+        );
+        sync_get_method(b, "GetCpuFrequencies", "frequencies", PowerDaemon::get_cpu_frequencies);
+        sync_get_method(b, "GetThermalState", "state", PowerDaemon::get_thermal_state);
+        sync_get_method(b, "GetTemperatures", "temperatures", PowerDaemon::get_temperatures);
+        sync_get_method(b, "GetCpuTemperature", "temperature", PowerDaemon::get_cpu_temperature);
+        sync_get_method(b, "GetModel", "model", PowerDaemon::get_model);
+        sync_get_method(b, "GetFirmwareVersions", "versions", PowerDaemon::get_firmware_versions);
+        sync_method(
+            b,
+            "SetDisplayBrightness",
+            ("percent", "fade_ms"),
+            (),
+            |d, (percent, fade_ms): (u8, u32)| d.set_display_brightness(percent, fade_ms),
+        );
+        sync_method(
+            b,
+            "IncreaseDisplayBrightness",
+            ("step",),
+            ("percent",),
+            |d, (step,): (u8,)| d.increase_display_brightness(step).map(|p| (p,)),
+        );
+        sync_method(
+            b,
+            "DecreaseDisplayBrightness",
+            ("step",),
+            ("percent",),
+            |d, (step,): (u8,)| d.decrease_display_brightness(step).map(|p| (p,)),
+        );
+        sync_method(
+            b,
+            "IncreaseKeyboardBrightness",
+            ("device",),
+            ("percent",),
+            |d, (device,): (i32,)| d.increase_keyboard_brightness(device).map(|p| (p,)),
+        );
+        sync_method(
+            b,
+            "DecreaseKeyboardBrightness",
+            ("device",),
+            ("percent",),
+            |d, (device,): (i32,)| d.decrease_keyboard_brightness(device).map(|p| (p,)),
+        );
+        sync_method(
+            b,
+            "SetExternalBrightness",
+            ("display", "percent"),
+            (),
+            |d, (display, percent): (u32, u8)| d.set_external_brightness(display, percent),
+        );
+        sync_method(
+            b,
+            "SetKeyboardColor",
+            ("zone", "color"),
+            (),
+            |d, (zone, color): (String, String)| d.set_keyboard_color(&zone, &color),
+        );
+        sync_get_method(b, "GetFanSpeeds", "rpms", PowerDaemon::get_fan_speeds);
+        sync_method(b, "SetFanDuty", ("index", "percent"), (), |d, (index, percent): (u32, u8)| {
+            d.set_fan_duty(index, percent)
+        });
+        sync_set_method(b, "SetFanCurve", "curve", |d, s: String| d.set_fan_curve(&s));
+        sync_set_method(b, "SetAutoProfileSwitch", "enabled", PowerDaemon::set_auto_profile_switch);
+        sync_action_method(b, "ReloadConfig", PowerDaemon::reload_config);
+        sync_get_method(b, "GetTurbo", "enabled", PowerDaemon::get_turbo);
+        sync_set_method(b, "SetTurbo", "enabled", PowerDaemon::set_turbo);
+        sync_get_method(b, "GetSmt", "enabled", PowerDaemon::get_smt);
+        sync_set_method(b, "SetSmt", "enabled", PowerDaemon::set_smt);
+        sync_set_method(b, "SetOnlineCores", "count", PowerDaemon::set_online_cores);
+        sync_set_method(b, "SetAutoBrightness", "enabled", PowerDaemon::set_auto_brightness);
+        sync_set_method(b, "SetKeyboardTimeout", "seconds", PowerDaemon::set_keyboard_timeout);
+        sync_get_method(b, "GetPlatformProfile", "profile", PowerDaemon::get_platform_profile);
+        sync_get_method(b, "GetBatteryHealth", "health", PowerDaemon::get_battery_health);
+        sync_get_method(b, "GetPowerDraw", "watts", PowerDaemon::get_power_draw);
+        sync_get_method(b, "GetBatteryStatus", "status", PowerDaemon::get_battery_status);
+        sync_get_method(
+            b,
+            "GetAvailableGovernors",
+            "governors",
+            PowerDaemon::get_available_governors,
+        );
+        sync_get_method(b, "GetGovernors", "governors", PowerDaemon::get_governors);
+        sync_set_method(b, "SetGovernor", "governor", |d, s: String| d.set_governor(&s));
+        sync_method(
+            b,
+            "SetCoreGovernor",
+            ("core", "governor"),
+            (),
+            |d, (core, governor): (u32, String)| d.set_core_governor(core, &governor),
+        );
+        sync_method(
+            b,
+            "SetCpuFrequency",
+            ("core", "frequency"),
+            (),
+            |d, (core, frequency): (u32, u32)| d.set_cpu_frequency(core, frequency),
+        );
+        sync_get_method(b, "GetPerfPercent", "percent", PowerDaemon::get_perf_percent);
+        sync_set_method(b, "SetMinPerfPercent", "percent", PowerDaemon::set_min_perf_percent);
+        sync_set_method(b, "SetMaxPerfPercent", "percent", PowerDaemon::set_max_perf_percent);
+        // Read-only properties mirroring the state behind the methods, for clients which use
+        // the freedesktop Properties interface. PropertiesChanged is emitted by the methods
+        // which change them.
+        b.property::<String, _>("Profile").get(|_, d| Ok(d.power_profile.clone()));
+        b.property::<String, _>("Graphics").get(|_, d| {
+            d.graphics.get_vendor().map(|m| m.to_string()).map_err(IntoMethodErr::into_method_err)
+        });
+        b.property::<bool, _>("GraphicsPower")
+            .get(|_, d| d.graphics.get_power().map_err(IntoMethodErr::into_method_err));
+        b.property::<(u8, u8), _>("ChargeThresholds")
+            .get(|_, _| get_charge_thresholds().map_err(IntoMethodErr::into_method_err));
         b.signal::<(u64,), _>("HotPlugDetect", ("port",));
         b.signal::<(&str,), _>("PowerProfileSwitch", ("profile",));
+        b.signal::<(bool,), _>("GraphicsSwitchComplete", ("reboot_required",));
     });
     cr.insert(DBUS_PATH, &[iface_token], daemon);
 
     let cr = Arc::new(std::sync::Mutex::new(cr));
+    let sleep_cr = cr.clone();
+    let loop_cr = cr.clone();
     c.start_receive(
         MatchRule::new_method_call(),
         Box::new(move |msg, c| {
@@ -296,6 +1290,19 @@ pub async fn daemon() -> Result<(), String> {
         }),
     );
 
+    // The match is removed when dropped, so it must live for as long as the daemon
+    let _sleep_match = c
+        .add_match(MatchRule::new_signal("org.freedesktop.login1.Manager", "PrepareForSleep"))
+        .await
+        .map_err(err_str)?
+        .cb(move |_, (suspending,): (bool,)| {
+            let mut cr = sleep_cr.lock().unwrap();
+            if let Some(daemon) = cr.data_mut::<PowerDaemon>(&DBUS_PATH.into()) {
+                daemon.prepare_for_sleep(suspending);
+            }
+            true
+        });
+
     // Spawn hid backlight daemon
     let _hid_backlight = thread::spawn(hid_backlight::daemon);
 
@@ -319,8 +1326,24 @@ pub async fn daemon() -> Result<(), String> {
     while CONTINUE.load(Ordering::SeqCst) {
         delay_for(Duration::from_millis(1000)).await;
 
+        while let Ok(curve) = fan_curve_rx.try_recv() {
+            log::info!("Setting fan curve: {:?}", curve);
+            fan_daemon.set_curve(curve);
+        }
+
         fan_daemon.step();
 
+        if let Some(daemon) = loop_cr.lock().unwrap().data_mut::<PowerDaemon>(&DBUS_PATH.into()) {
+            daemon.pending_profile_step();
+            daemon.ac_step();
+            daemon.lid_step();
+            daemon.low_battery_step();
+            daemon.backlight_step();
+            let on_battery = daemon.ac_online == Some(false);
+            daemon.kbd_timeout.step(on_battery);
+            daemon.auto_brightness.step();
+        }
+
         let hpd = hpd();
         for i in 0..hpd.len() {
             if hpd[i] != last[i] && hpd[i] {
@@ -344,10 +1367,58 @@ pub async fn daemon() -> Result<(), String> {
     }
 
     log::info!("daemon exited from loop");
+    fan::restore_automatic();
     Ok(())
 }
 
-fn sync_method<IA, OA, F>(
+/// Converts the error of a method handler into a D-Bus error reply.
+trait IntoMethodErr {
+    fn into_method_err(self) -> MethodErr;
+}
+
+impl IntoMethodErr for String {
+    fn into_method_err(self) -> MethodErr { MethodErr::failed(&self) }
+}
+
+/// Graphics errors are named, so that clients can tell them apart without matching on the
+/// message.
+impl IntoMethodErr for GraphicsDeviceError {
+    fn into_method_err(self) -> MethodErr {
+        MethodErr::from((self.dbus_error_name(), self.to_string()))
+    }
+}
+
+/// DBus wrapper for a graphics switch. Regenerating the initramfs may take minutes, so the
+/// switch runs on a worker thread, and other methods are handled while it runs.
+fn switch_graphics_method(
+    b: &mut IfaceBuilder<PowerDaemon>,
+    name: &'static str,
+    force: bool,
+    c: Arc<SyncConnection>,
+) {
+    b.method_with_cr_async(name, ("vendor",), (), move |mut ctx, _cr, (vendor,): (String,)| {
+        log::info!("DBUS Received {}({:?}) method", name, vendor);
+        let c = c.clone();
+        let receiver = spawn_switch(move || switch_graphics(&c, &vendor, force));
+        async move {
+            let result = loop {
+                // Bound first, so that no borrow of the receiver is held across the delay.
+                let received = receiver.try_recv();
+                match received {
+                    Ok(result) => break result.map_err(IntoMethodErr::into_method_err),
+                    Err(mpsc::TryRecvError::Empty) => delay_for(Duration::from_millis(100)).await,
+                    Err(mpsc::TryRecvError::Disconnected) => {
+                        break Err(MethodErr::failed(&"graphics switch did not complete"))
+                    }
+                }
+            };
+
+            ctx.reply(result)
+        }
+    });
+}
+
+fn sync_method<IA, OA, E, F>(
     b: &mut IfaceBuilder<PowerDaemon>,
     name: &'static str,
     input_args: IA::strs,
@@ -356,15 +1427,13 @@ fn sync_method<IA, OA, F>(
 ) where
     IA: arg::ArgAll + arg::ReadAll + Debug,
     OA: arg::ArgAll + arg::AppendAll,
-    F: Fn(&mut PowerDaemon, IA) -> Result<OA, String> + Send + 'static,
+    E: IntoMethodErr,
+    F: Fn(&mut PowerDaemon, IA) -> Result<OA, E> + Send + 'static,
 {
     b.method_with_cr(name, input_args, output_args, move |ctx, cr, args| {
         log::info!("DBUS Received {}{:?} method", name, args);
         match cr.data_mut(ctx.path()) {
-            Some(daemon) => match f(daemon, args) {
-                Ok(ret) => Ok(ret),
-                Err(err) => Err(MethodErr::failed(&err)),
-            },
+            Some(daemon) => f(daemon, args).map_err(IntoMethodErr::into_method_err),
             None => Err(MethodErr::no_path(ctx.path())),
         }
     });
@@ -379,27 +1448,80 @@ where
 }
 
 /// DBus wrapper for method taking no arguments and returning one value
-fn sync_get_method<T, F>(
+fn sync_get_method<T, E, F>(
     b: &mut IfaceBuilder<PowerDaemon>,
     name: &'static str,
     output_arg: &'static str,
     f: F,
 ) where
     T: arg::Arg + arg::Append + Debug,
-    F: Fn(&mut PowerDaemon) -> Result<T, String> + Send + 'static,
+    E: IntoMethodErr,
+    F: Fn(&mut PowerDaemon) -> Result<T, E> + Send + 'static,
 {
     sync_method(b, name, (), (output_arg,), move |d, _: ()| f(d).map(|x| (x,)));
 }
 
 /// DBus wrapper for method taking one argument and returning no values
-fn sync_set_method<T, F>(
+fn sync_set_method<T, E, F>(
     b: &mut IfaceBuilder<PowerDaemon>,
     name: &'static str,
     input_arg: &'static str,
     f: F,
 ) where
     T: arg::Arg + for<'z> arg::Get<'z> + Debug,
-    F: Fn(&mut PowerDaemon, T) -> Result<(), String> + Send + 'static,
+    E: IntoMethodErr,
+    F: Fn(&mut PowerDaemon, T) -> Result<(), E> + Send + 'static,
 {
     sync_method(b, name, (input_arg,), (), move |d, (arg,)| f(d, arg))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{sync::Barrier, thread};
+
+    // The guard is global, so its tests are kept in one to avoid running concurrently.
+    #[test]
+    fn switch_guard_excludes_switches_and_profile_changes() {
+        const THREADS: usize = 8;
+        let barrier = Arc::new(Barrier::new(THREADS));
+        let handles: Vec<_> = (0..THREADS)
+            .map(|_| {
+                let barrier = barrier.clone();
+                thread::spawn(move || {
+                    barrier.wait();
+                    let guard = SwitchGuard::try_acquire();
+                    let acquired = guard.is_some();
+                    // Hold the guard until every thread has made its attempt.
+                    barrier.wait();
+                    acquired
+                })
+            })
+            .collect();
+
+        let acquired = handles.into_iter().map(|h| h.join().unwrap()).filter(|&a| a).count();
+        assert_eq!(acquired, 1);
+        assert!(!graphics::is_switching());
+
+        // A profile change requested during a switch is refused.
+        let switch = SwitchGuard::try_acquire().unwrap();
+        assert_eq!(begin_profile_change().err().as_ref().map(String::as_str), Some(BUSY_ERROR));
+        drop(switch);
+
+        // A switch requested during a profile change is refused in turn, before the hardware
+        // is looked at.
+        let profile = begin_profile_change().unwrap();
+        let switch = spawn_switch(|| {
+            let graphics = Graphics::new_without_rescan().map_err(GraphicsDeviceError::SysFs)?;
+            graphics.set_vendor("integrated", false, false).map(|_| ())
+        });
+
+        match switch.recv().unwrap() {
+            Err(GraphicsDeviceError::Busy) => (),
+            other => panic!("expected Busy, got {:?}", other),
+        }
+
+        drop(profile);
+        assert!(begin_profile_change().is_ok());
+    }
+}
@@ -0,0 +1,161 @@
+use crate::backlight::{self, BacklightExt};
+use std::{
+    cmp, fs, io,
+    path::{Path, PathBuf},
+};
+use sysfs_class::Brightness;
+
+const IIO_PATH: &str = "/sys/bus/iio/devices";
+
+/// The duration of each fade to a new brightness.
+const FADE_MS: u64 = 500;
+
+/// Brightness changes smaller than this percentage are skipped, to avoid flicker.
+const HYSTERESIS_PERCENT: u64 = 3;
+
+/// Adjusts the display backlight according to an ambient light sensor. The feature disables
+/// itself when the brightness is changed by anything else.
+pub struct AutoBrightness {
+    sensor:   Option<PathBuf>,
+    /// Pairs of illuminance in lux and brightness percentage, in increasing order
+    curve:    Vec<(u32, u8)>,
+    // The brightness last written, to detect changes made by other programs
+    last_set: Option<u64>,
+}
+
+impl AutoBrightness {
+    pub fn new(mut curve: Vec<(u32, u8)>) -> Self {
+        curve.sort_by_key(|&(lux, _)| lux);
+        AutoBrightness { sensor: None, curve, last_set: None }
+    }
+
+    pub fn is_enabled(&self) -> bool { self.sensor.is_some() }
+
+    /// Replaces the curve, without enabling or disabling the feature.
+    pub fn set_curve(&mut self, mut curve: Vec<(u32, u8)>) {
+        curve.sort_by_key(|&(lux, _)| lux);
+        self.curve = curve;
+    }
+
+    pub fn set_enabled(&mut self, enabled: bool) -> io::Result<()> {
+        if !enabled {
+            self.disable();
+            return Ok(());
+        }
+
+        if self.sensor.is_none() {
+            let sensor = find_sensor().ok_or_else(|| {
+                io::Error::new(io::ErrorKind::NotFound, "no ambient light sensor found")
+            })?;
+            log::info!("Enabling auto brightness with {:?}", sensor);
+            self.sensor = Some(sensor);
+            self.last_set = None;
+        }
+
+        Ok(())
+    }
+
+    /// Forgets the brightness last written, after the display backlights have changed, so that
+    /// a new primary backlight is not mistaken for a manual change.
+    pub fn backlights_changed(&mut self) { self.last_set = None; }
+
+    /// Stops adjusting the brightness, such as when the user sets it manually.
+    pub fn disable(&mut self) {
+        if self.sensor.take().is_some() {
+            log::info!("Disabling auto brightness");
+        }
+    }
+
+    /// Reads the sensor, and fades the backlight to the brightness that the curve maps it to.
+    pub fn step(&mut self) {
+        let sensor = match self.sensor {
+            Some(ref sensor) => sensor,
+            None => return,
+        };
+
+        let lux = match read_illuminance(sensor) {
+            Ok(lux) => lux,
+            Err(why) => {
+                log::warn!("failed to read ambient light sensor: {}", why);
+                return;
+            }
+        };
+
+        let result = backlight::primary().and_then(|backlight| {
+            let current = backlight.brightness()?;
+            if self.last_set.map_or(false, |last| last != current) {
+                log::info!("Display brightness was changed manually");
+                self.sensor = None;
+                return Ok(());
+            }
+
+            let max = backlight.max_brightness()?;
+            let target = max * u64::from(map_curve(&self.curve, lux)) / 100;
+            let delta = if target > current { target - current } else { current - target };
+            if delta * 100 >= max * HYSTERESIS_PERCENT {
+                // The fade runs on a worker thread, so the brightness it ends at is recorded.
+                let target = backlight.clamp_brightness(target)?;
+                self.last_set = Some(target);
+                backlight::fade_in_background(backlight, move |backlight| {
+                    backlight.set_brightness_smooth(target, FADE_MS)
+                });
+            } else {
+                self.last_set = Some(current);
+            }
+
+            Ok(())
+        });
+
+        if let Err(why) = result {
+            log::warn!("failed to set auto brightness: {}", why);
+        }
+    }
+}
+
+fn find_sensor() -> Option<PathBuf> {
+    fs::read_dir(IIO_PATH)
+        .ok()?
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .find(|path| path.join("in_illuminance_raw").exists())
+}
+
+fn read_f64(path: &Path) -> io::Result<f64> {
+    fs::read_to_string(path)?
+        .trim()
+        .parse::<f64>()
+        .map_err(|why| io::Error::new(io::ErrorKind::InvalidData, why))
+}
+
+/// The illuminance in lux, from the raw value and the optional offset and scale of the sensor.
+fn read_illuminance(sensor: &Path) -> io::Result<u32> {
+    let raw = read_f64(&sensor.join("in_illuminance_raw"))?;
+    let offset = read_f64(&sensor.join("in_illuminance_offset")).unwrap_or(0.0);
+    let scale = read_f64(&sensor.join("in_illuminance_scale")).unwrap_or(1.0);
+    Ok(((raw + offset) * scale).max(0.0) as u32)
+}
+
+/// Linearly interpolates the brightness percentage for the illuminance between the points of
+/// the curve.
+fn map_curve(curve: &[(u32, u8)], lux: u32) -> u8 {
+    let (first, last) = match (curve.first(), curve.last()) {
+        (Some(first), Some(last)) => (first, last),
+        _ => return 100,
+    };
+
+    if lux <= first.0 {
+        return cmp::min(first.1, 100);
+    }
+
+    for window in curve.windows(2) {
+        let ((lux_a, pct_a), (lux_b, pct_b)) = (window[0], window[1]);
+        if lux <= lux_b && lux_b > lux_a {
+            let pct = i64::from(pct_a)
+                + (i64::from(pct_b) - i64::from(pct_a)) * i64::from(lux - lux_a)
+                    / i64::from(lux_b - lux_a);
+            return cmp::min(pct, 100) as u8;
+        }
+    }
+
+    cmp::min(last.1, 100)
+}
@@ -0,0 +1,36 @@
+use std::time::{Duration, Instant};
+use sysfs_class::{Backlight, SysClass};
+
+/// How often the backlights are enumerated again.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Tracks the display backlights which are present by periodically polling sysfs, so that panels
+/// and docks which are plugged in or unplugged while the daemon runs are picked up within a few
+/// seconds.
+pub struct BacklightWatch {
+    ids:       Vec<String>,
+    last_poll: Instant,
+}
+
+impl BacklightWatch {
+    pub fn new() -> Self { BacklightWatch { ids: backlight_ids(), last_poll: Instant::now() } }
+
+    /// Enumerates the backlights again if the poll interval has elapsed, returning the IDs of those
+    /// which were added and those which were removed since the last poll.
+    pub fn step(&mut self) -> (Vec<String>, Vec<String>) {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return (Vec::new(), Vec::new());
+        }
+
+        self.last_poll = Instant::now();
+        let ids = backlight_ids();
+        let added = ids.iter().filter(|id| !self.ids.contains(id)).cloned().collect();
+        let removed = self.ids.iter().filter(|id| !ids.contains(id)).cloned().collect();
+        self.ids = ids;
+        (added, removed)
+    }
+}
+
+fn backlight_ids() -> Vec<String> {
+    Backlight::iter().filter_map(Result::ok).map(|backlight| backlight.id().to_owned()).collect()
+}
@@ -0,0 +1,145 @@
+use super::sleep::snapshot;
+use crate::backlight::{fade_in_background, KeyboardBacklightExt};
+use std::{
+    convert::TryInto,
+    fs::{self, File},
+    io::{self, Read},
+    mem,
+    os::unix::fs::OpenOptionsExt,
+    time::{Duration, Instant},
+};
+use sysfs_class::{Leds, SysClass};
+
+const INPUT_PATH: &str = "/dev/input";
+
+/// The duration of the fade when turning the keyboard backlight off, and back on.
+const FADE_MS: u64 = 500;
+
+/// Fades the keyboard backlight off after a period without input while on battery, and fades
+/// it back to its prior brightness on the next input, or when AC power returns.
+pub struct KeyboardTimeout {
+    timeout:       Option<Duration>,
+    inputs:        Vec<File>,
+    last_activity: Instant,
+    // The brightness of each keyboard backlight before it was turned off
+    saved:         Option<Vec<(String, u64)>>,
+}
+
+impl KeyboardTimeout {
+    pub fn new() -> Self {
+        KeyboardTimeout {
+            timeout:       None,
+            inputs:        Vec::new(),
+            last_activity: Instant::now(),
+            saved:         None,
+        }
+    }
+
+    /// Sets the period without input after which the keyboard backlight is turned off. A
+    /// timeout of 0 disables the feature.
+    pub fn set_timeout(&mut self, seconds: u32) -> io::Result<()> {
+        if seconds == 0 {
+            self.timeout = None;
+            self.inputs.clear();
+            self.wake();
+            return Ok(());
+        }
+
+        if self.inputs.is_empty() {
+            self.inputs = open_inputs()?;
+        }
+
+        self.timeout = Some(Duration::from_secs(u64::from(seconds)));
+        self.last_activity = Instant::now();
+        Ok(())
+    }
+
+    /// Checks for input since the last step, and turns the keyboard backlight off or back on.
+    /// The timeout only applies on battery, as it is meant to save power. The fades run on
+    /// worker threads, so that the daemon is not held up by them.
+    pub fn step(&mut self, on_battery: bool) {
+        let timeout = match self.timeout {
+            Some(timeout) => timeout,
+            None => return,
+        };
+
+        // Every device is drained, so that stale events are not seen on the next step.
+        let mut active = false;
+        for input in &mut self.inputs {
+            active |= has_activity(input);
+        }
+
+        if active || !on_battery {
+            self.last_activity = Instant::now();
+            self.wake();
+        } else if self.saved.is_none() && self.last_activity.elapsed() >= timeout {
+            log::info!("Turning off keyboard backlight after {}s without input", timeout.as_secs());
+            self.saved = Some(snapshot(Leds::iter_keyboards()));
+            for keyboard in Leds::iter_keyboards().filter_map(Result::ok) {
+                fade_in_background(keyboard, |keyboard| keyboard.fade_to(0, FADE_MS));
+            }
+        }
+    }
+
+    /// Fades the keyboard backlight back to its prior brightness, if it was turned off.
+    fn wake(&mut self) {
+        let saved = match self.saved.take() {
+            Some(saved) => saved,
+            None => return,
+        };
+
+        log::info!("Restoring keyboard backlight");
+        for (id, brightness) in saved {
+            match Leds::new(&id) {
+                Ok(keyboard) => fade_in_background(keyboard, move |keyboard| {
+                    keyboard.fade_to(brightness, FADE_MS)
+                }),
+                // The keyboard may have been unplugged
+                Err(why) => log::debug!("keyboard backlight {} is no longer present: {}", id, why),
+            }
+        }
+    }
+}
+
+/// Opens each input event device without blocking, so that pending events can be drained.
+fn open_inputs() -> io::Result<Vec<File>> {
+    let mut inputs = Vec::new();
+    for entry in fs::read_dir(INPUT_PATH)?.filter_map(Result::ok) {
+        if !entry.file_name().to_string_lossy().starts_with("event") {
+            continue;
+        }
+
+        match fs::OpenOptions::new().read(true).custom_flags(libc::O_NONBLOCK).open(entry.path()) {
+            Ok(file) => inputs.push(file),
+            Err(why) => log::warn!("failed to open {:?}: {}", entry.path(), why),
+        }
+    }
+
+    Ok(inputs)
+}
+
+/// Drains the pending events of an input device, returning whether any of them were key
+/// presses or pointer movement.
+fn has_activity(input: &mut File) -> bool {
+    // Key presses, and relative and absolute pointer movement
+    const EV_KEY: u16 = 1;
+    const EV_ABS: u16 = 3;
+
+    let size = mem::size_of::<libc::input_event>();
+    let mut buffer = vec![0; size * 64];
+    let mut active = false;
+
+    while let Ok(read) = input.read(&mut buffer) {
+        if read == 0 {
+            break;
+        }
+
+        // The type of each event follows its timestamp, and precedes its code and value.
+        active |= buffer[..read].chunks_exact(size).any(|event| {
+            let kind = u16::from_ne_bytes(event[size - 8..size - 6].try_into().unwrap());
+            (EV_KEY..=EV_ABS).contains(&kind)
+        });
+    }
+
+    active
+}
@@ -0,0 +1,53 @@
+use std::{
+    process::{Command, Stdio},
+    thread,
+    time::{Duration, Instant},
+};
+
+/// How long a hook may run before it is killed.
+const TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Runs the configured profile change script with the new profile as its only argument. The
+/// script runs on its own thread, so that a hanging hook cannot block the daemon, and is killed
+/// once it exceeds the timeout.
+pub fn run(script: &str, profile: &str) {
+    let mut child = match Command::new(script)
+        .arg(profile)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(why) => {
+            log::error!("failed to run profile hook {}: {}", script, why);
+            return;
+        }
+    };
+
+    let script = script.to_owned();
+    thread::spawn(move || {
+        let started = Instant::now();
+        loop {
+            match child.try_wait() {
+                Ok(Some(status)) => {
+                    if !status.success() {
+                        log::warn!("profile hook {} exited with {}", script, status);
+                    }
+                    return;
+                }
+                Ok(None) if started.elapsed() >= TIMEOUT => {
+                    log::warn!("profile hook {} timed out, killing it", script);
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    return;
+                }
+                Ok(None) => thread::sleep(POLL_INTERVAL),
+                Err(why) => {
+                    log::error!("failed to wait for profile hook {}: {}", script, why);
+                    return;
+                }
+            }
+        }
+    });
+}
@@ -1,10 +1,13 @@
 use super::pci_runtime_pm_support;
 use crate::{
+    config::ProfileConfig,
+    cpu, cpufreq,
     disks::{DiskPower, Disks},
     errors::{
         BacklightError, DiskPowerError, ModelError, PciDeviceError, ProfileError, ScsiHostError,
     },
     kernel_parameters::{DeviceList, Dirty, KernelParameter, LaptopMode},
+    platform_profile,
     radeon::RadeonDevice,
 };
 use intel_pstate::{PState, PStateError};
@@ -31,7 +34,7 @@ macro_rules! catch {
 }
 
 /// Sets parameters for the balanced profile.
-pub fn balanced(errors: &mut Vec<ProfileError>, set_brightness: bool) {
+pub fn balanced(errors: &mut Vec<ProfileError>, set_brightness: bool, config: &ProfileConfig) {
     // The dirty kernel parameter controls how often the OS will sync data to disks. The less
     // frequently this occurs, the more power can be saved, yet the higher the risk of sudden
     // power loss causing loss of data. 15s is a resonable number.
@@ -70,19 +73,49 @@ pub fn balanced(errors: &mut Vec<ProfileError>, set_brightness: bool) {
     // Control Intel PState values, if they exist.
     catch!(errors, pstate_values(0, 100, false));
 
+    // Permit the full frequency range, with a governor which scales with the load. Drivers
+    // without schedutil, such as intel_pstate, scale with the load under powersave.
+    let governors = cpufreq::available_governors(0).unwrap_or_default();
+    let default =
+        if governors.iter().any(|g| g == "schedutil") { "schedutil" } else { "powersave" };
+    cpufreq_profile(config.governor.as_deref().unwrap_or(default), cpufreq::balanced);
+
+    // Hint to the CPU that performance is preferred, with some regard for energy usage.
+    energy_performance_preference(config, "balance_performance");
+
+    // Biases the CPU towards performance, while saving some energy, on Intel systems with the
+    // energy performance bias MSR.
+    energy_perf_bias(6);
+
+    // Selects the matching ACPI platform profile on firmware which provides one.
+    set_platform_profile("balanced");
+
+    // Control frequency boosting on drivers which do not use the Intel PState no_turbo value.
+    cpu_boost(true);
+
     if let Some(model_profiles) = ModelProfiles::new() {
         catch!(errors, model_profiles.balanced.set());
     }
 }
 
 /// Sets parameters for the performance profile
-pub fn performance(errors: &mut Vec<ProfileError>, _set_brightness: bool) {
+pub fn performance(errors: &mut Vec<ProfileError>, _set_brightness: bool, config: &ProfileConfig) {
     Dirty::default().set_max_lost_work(15);
     LaptopMode::default().set(b"0");
     RadeonDevice::get_devices().for_each(|dev| dev.set_profiles("high", "performance", "auto"));
     catch!(errors, set_disk_power(254, 300_000));
     catch!(errors, scsi_host_link_time_pm_policy(&["med_power_with_dipm", "max_performance"]));
     catch!(errors, pstate_values(50, 100, false));
+    cpufreq_profile(config.governor.as_deref().unwrap_or("performance"), cpufreq::performance);
+    energy_performance_preference(config, "performance");
+    energy_perf_bias(0);
+    set_platform_profile("performance");
+    cpu_boost(true);
+
+    // Restore any cores which were taken offline to save power.
+    if let Err(why) = cpu::online_all_cores() {
+        log::warn!("failed to bring all cores online: {}", why);
+    }
 
     if pci_runtime_pm_support() {
         catch!(errors, pci_device_runtime_pm(RuntimePowerManagement::Off));
@@ -94,13 +127,18 @@ pub fn performance(errors: &mut Vec<ProfileError>, _set_brightness: bool) {
 }
 
 /// Sets parameters for the battery profile
-pub fn battery(errors: &mut Vec<ProfileError>, set_brightness: bool) {
+pub fn battery(errors: &mut Vec<ProfileError>, set_brightness: bool, config: &ProfileConfig) {
     Dirty::default().set_max_lost_work(15);
     LaptopMode::default().set(b"2");
     RadeonDevice::get_devices().for_each(|dev| dev.set_profiles("low", "battery", "low"));
     catch!(errors, set_disk_power(127, 15000));
     catch!(errors, scsi_host_link_time_pm_policy(&["min_power", "min_power"]));
     catch!(errors, pstate_values(0, 50, true));
+    cpufreq_profile(config.governor.as_deref().unwrap_or("powersave"), cpufreq::powersave);
+    energy_performance_preference(config, "power");
+    energy_perf_bias(15);
+    set_platform_profile("low-power");
+    cpu_boost(false);
 
     if set_brightness {
         catch!(errors, iterate_backlights(Backlight::iter(), &Brightness::set_if_lower_than, 10));
@@ -116,6 +154,43 @@ pub fn battery(errors: &mut Vec<ProfileError>, set_brightness: bool) {
     }
 }
 
+/// Sets the CPU parameters of a custom profile. Other parameters are left as the previous
+/// profile set them.
+pub fn custom(errors: &mut Vec<ProfileError>, min: u8, max: u8, no_turbo: bool, governor: &str) {
+    if PState::new().is_ok() {
+        catch!(errors, pstate_values(min, max, no_turbo));
+    } else {
+        // Without intel_pstate, the percentages are applied through cpufreq instead. The minimum
+        // is lowered first, so that the new maximum is never below the old minimum.
+        let limits = cpufreq::set_min_percent(0).and_then(|_| cpufreq::set_max_percent(max));
+        if let Err(why) = limits {
+            log::warn!("failed to set cpufreq maximum frequency: {}", why);
+        }
+
+        if let Err(why) = cpufreq::set_min_percent(min) {
+            log::warn!("failed to set cpufreq minimum frequency: {}", why);
+        }
+    }
+
+    cpu_boost(!no_turbo);
+
+    let cores = cpufreq::cpufreq_cores();
+    let mapping: Vec<(usize, &str)> = cores.into_iter().map(|core| (core, governor)).collect();
+    cpufreq::set_governors(&mapping);
+}
+
+/// Sets the cpufreq governor of a profile, with its frequency limits. With intel_pstate, the
+/// limits are set through its percentages instead, so only the governor is set.
+fn cpufreq_profile(governor: &str, set_limits: fn(&str) -> io::Result<()>) {
+    if PState::new().is_ok() {
+        let cores = cpufreq::cpufreq_cores();
+        let mapping: Vec<(usize, &str)> = cores.into_iter().map(|core| (core, governor)).collect();
+        cpufreq::set_governors(&mapping);
+    } else if let Err(why) = set_limits(governor) {
+        log::warn!("failed to set cpufreq {} profile: {}", governor, why);
+    }
+}
+
 /// Controls the Intel PState values.
 fn pstate_values(min: u8, max: u8, no_turbo: bool) -> Result<(), PStateError> {
     if let Ok(pstate) = PState::new() {
@@ -127,6 +202,56 @@ fn pstate_values(min: u8, max: u8, no_turbo: bool) -> Result<(), PStateError> {
     Ok(())
 }
 
+/// Controls the global cpufreq boost setting, if it exists.
+fn cpu_boost(enabled: bool) {
+    match cpufreq::set_boost(enabled) {
+        Ok(true) => (),
+        Ok(false) => {
+            if PState::new().is_err() {
+                log::debug!("neither cpufreq boost nor intel_pstate no_turbo is available");
+            }
+        }
+        Err(why) => log::warn!("failed to set cpufreq boost: {}", why),
+    }
+}
+
+/// Sets the ACPI platform profile. Systems without the interface are left untouched.
+fn set_platform_profile(profile: &str) {
+    if let Err(why) = platform_profile::set(profile) {
+        log::warn!("failed to set platform profile to {}: {}", profile, why);
+    }
+}
+
+/// Sets the energy performance bias on every core. The bias is only available with the msr or
+/// intel_pstate support, so systems without it are only logged once.
+fn energy_perf_bias(bias: u8) {
+    for core in cpufreq::online_cpus() {
+        match cpufreq::set_energy_perf_bias(core, bias) {
+            Ok(()) => (),
+            Err(ref why) if why.kind() == io::ErrorKind::NotFound => {
+                log::debug!("energy performance bias is not supported");
+                return;
+            }
+            Err(why) => log::warn!("cpu{}: failed to set energy performance bias: {}", core, why),
+        }
+    }
+}
+
+/// Sets the energy performance preference on every core, unless the profile configures its own.
+/// Systems whose cpufreq driver does not support EPP are silently left untouched.
+fn energy_performance_preference(config: &ProfileConfig, default: &str) {
+    let pref = config.energy_performance_preference.as_deref().unwrap_or(default);
+    for core in cpufreq::cpufreq_cores() {
+        match cpufreq::set_energy_performance_preference(core, pref) {
+            Ok(()) => (),
+            Err(ref why) if why.kind() == io::ErrorKind::NotFound => return,
+            Err(why) => {
+                log::warn!("cpu{}: failed to set energy performance preference: {}", core, why);
+            }
+        }
+    }
+}
+
 /// Iterates across all backlights in the supplied iterator, executing the given strategy function
 /// on each discovered backlight source.
 fn iterate_backlights<B: Brightness>(
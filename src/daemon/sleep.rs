@@ -0,0 +1,53 @@
+use std::io;
+use sysfs_class::{Backlight, Brightness, Leds, SysClass};
+
+/// The brightness of each display and keyboard backlight, recorded before suspending so that it
+/// can be restored after resuming.
+pub struct BacklightSnapshot {
+    backlights: Vec<(String, u64)>,
+    keyboards:  Vec<(String, u64)>,
+}
+
+impl BacklightSnapshot {
+    pub fn new() -> Self {
+        BacklightSnapshot {
+            backlights: snapshot(Backlight::iter()),
+            keyboards:  snapshot(Leds::iter_keyboards()),
+        }
+    }
+
+    /// Stops tracking a display backlight which has been removed.
+    pub fn forget_backlight(&mut self, id: &str) {
+        self.backlights.retain(|(other, _)| other != id);
+    }
+
+    pub fn restore(&self) {
+        restore::<Backlight>("backlight", &self.backlights);
+        restore::<Leds>("keyboard backlight", &self.keyboards);
+    }
+}
+
+pub(super) fn snapshot<T: Brightness + SysClass>(
+    devices: impl Iterator<Item = io::Result<T>>,
+) -> Vec<(String, u64)> {
+    devices
+        .filter_map(Result::ok)
+        .filter_map(|device| {
+            device.brightness().ok().map(|brightness| (device.id().to_owned(), brightness))
+        })
+        .collect()
+}
+
+pub(super) fn restore<T: Brightness + SysClass>(kind: &str, values: &[(String, u64)]) {
+    for (id, brightness) in values {
+        match T::new(id) {
+            Ok(device) => {
+                if let Err(why) = device.set_brightness(*brightness) {
+                    log::warn!("failed to restore {} {}: {}", kind, id, why);
+                }
+            }
+            // The device may have been unplugged, such as a panel in a dock
+            Err(why) => log::debug!("{} {} is no longer present: {}", kind, id, why),
+        }
+    }
+}
@@ -0,0 +1,38 @@
+//! Brightness control of external displays through DDC/CI. This requires the `ddc` feature, as
+//! not every system has the I2C devices that it depends upon.
+
+#[cfg(feature = "ddc")]
+use crate::err_str;
+#[cfg(feature = "ddc")]
+use ddc_hi::{Ddc, Display};
+#[cfg(feature = "ddc")]
+use std::cmp;
+
+/// The VCP feature code for the luminance of a display.
+#[cfg(feature = "ddc")]
+const VCP_BRIGHTNESS: u8 = 0x10;
+
+/// Sets the brightness of the external display at `index`, as a percentage of its maximum
+/// brightness. Nothing is done if no displays support DDC/CI.
+#[cfg(feature = "ddc")]
+pub fn set_brightness(index: usize, percent: u8) -> Result<(), String> {
+    let mut displays = Display::enumerate();
+    if displays.is_empty() {
+        log::debug!("no displays with DDC/CI support were found");
+        return Ok(());
+    }
+
+    let count = displays.len();
+    let display = displays
+        .get_mut(index)
+        .ok_or_else(|| format!("display {} not found: {} displays available", index, count))?;
+
+    let max = display.handle.get_vcp_feature(VCP_BRIGHTNESS).map_err(err_str)?.maximum();
+    let value = u32::from(max) * u32::from(cmp::min(percent, 100)) / 100;
+    display.handle.set_vcp_feature(VCP_BRIGHTNESS, value as u16).map_err(err_str)
+}
+
+#[cfg(not(feature = "ddc"))]
+pub fn set_brightness(_index: usize, _percent: u8) -> Result<(), String> {
+    Err("built without support for DDC/CI".to_string())
+}
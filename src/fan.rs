@@ -1,12 +1,19 @@
 #![allow(clippy::inconsistent_digit_grouping)]
 
+use crate::model::Model;
 use std::{
     cell::Cell,
     cmp, fs, io,
+    path::PathBuf,
     process::{Command, Stdio},
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
 };
 use sysfs_class::{HwMon, SysClass};
 
+// Set when a fan has been given a manual duty, which pauses the automatic fan curve.
+static MANUAL_DUTY: AtomicBool = AtomicBool::new(false);
+
 #[derive(Debug, err_derive::Error)]
 pub enum FanDaemonError {
     #[error(display = "failed to collect hwmon devices: {}", _0)]
@@ -28,9 +35,9 @@ pub struct FanDaemon {
 
 impl FanDaemon {
     pub fn new(nvidia_exists: bool) -> Self {
-        let model = fs::read_to_string("/sys/class/dmi/id/product_version").unwrap_or_default();
+        let model = Model::detect();
         let mut daemon = FanDaemon {
-            curve: match model.trim() {
+            curve: match model.system76().unwrap_or_default() {
                 "thelio-major-r1" => FanCurve::threadripper2(),
                 "thelio-major-r2" | "thelio-major-r2.1" | "thelio-major-b1" | "thelio-major-b2"
                 | "thelio-major-b3" | "thelio-mega-r1" | "thelio-mega-r1.1" => FanCurve::hedt(),
@@ -150,8 +157,18 @@ impl FanDaemon {
         }
     }
 
+    /// Replaces the fan curve, and resumes automatic control if a manual duty was set
+    pub fn set_curve(&mut self, curve: FanCurve) {
+        restore_automatic();
+        self.curve = curve;
+    }
+
     /// Calculate the correct duty cycle and apply it to all fans
     pub fn step(&mut self) {
+        if MANUAL_DUTY.load(Ordering::SeqCst) {
+            return;
+        }
+
         if let Ok(()) = self.discover() {
             self.set_duty(self.get_temp().and_then(|temp| self.get_duty(temp)));
         }
@@ -306,6 +323,120 @@ impl FanCurve {
     }
 }
 
+impl FromStr for FanCurve {
+    type Err = String;
+
+    /// Parses comma-separated `temp:duty` points, in degrees Celsius and percent, such as
+    /// `45:30,65:40,88:100`. Temperatures must be strictly increasing, and duties must not
+    /// decrease.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut curve = FanCurve::default();
+        for point in s.split(',') {
+            let mut fields = point.trim().splitn(2, ':');
+            let (temp, duty) = match (fields.next(), fields.next()) {
+                (Some(temp), Some(duty)) => (temp.trim(), duty.trim()),
+                _ => return Err(format!("fan curve point '{}' is not temp:duty", point)),
+            };
+
+            let temp = temp
+                .parse::<i16>()
+                .ok()
+                .filter(|temp| *temp >= -100 && *temp <= 150)
+                .ok_or_else(|| format!("fan curve temperature '{}' is invalid", temp))?;
+            let duty = duty
+                .parse::<u16>()
+                .ok()
+                .filter(|duty| *duty <= 100)
+                .ok_or_else(|| format!("fan curve duty '{}' is invalid", duty))?;
+
+            if let Some(last) = curve.points.last() {
+                if temp * 100 <= last.temp {
+                    return Err("fan curve temperatures must be increasing".into());
+                } else if duty * 100 < last.duty {
+                    return Err("fan curve duties must not decrease".into());
+                }
+            }
+
+            curve = curve.append(temp * 100, duty * 100);
+        }
+
+        Ok(curve)
+    }
+}
+
+/// Every hwmon file named `{prefix}{n}{suffix}`, ordered by device and then by channel number.
+pub(crate) fn hwmon_channels(prefix: &str, suffix: &str) -> io::Result<Vec<PathBuf>> {
+    let mut channels = Vec::new();
+    for hwmon in HwMon::all()? {
+        let mut files = Vec::new();
+        for entry in fs::read_dir(hwmon.path())?.filter_map(Result::ok) {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            if name.len() > prefix.len() + suffix.len()
+                && name.starts_with(prefix)
+                && name.ends_with(suffix)
+            {
+                let channel = &name[prefix.len()..name.len() - suffix.len()];
+                if let Ok(channel) = channel.parse::<u32>() {
+                    files.push((channel, entry.path()));
+                }
+            }
+        }
+
+        files.sort();
+        channels.extend(files.into_iter().map(|(_, path)| path));
+    }
+
+    Ok(channels)
+}
+
+/// The speed of every fan that reports one, in RPM.
+pub fn get_fan_rpms() -> io::Result<Vec<u32>> {
+    hwmon_channels("fan", "_input")?
+        .iter()
+        .map(|path| {
+            fs::read_to_string(path)?
+                .trim()
+                .parse::<u32>()
+                .map_err(|why| io::Error::new(io::ErrorKind::InvalidData, why))
+        })
+        .collect()
+}
+
+/// Switches the PWM channel at `index` to manual control, with a duty of `percent`. This pauses
+/// the fan daemon until `restore_automatic` is called.
+pub fn set_fan_duty(index: usize, percent: u8) -> io::Result<()> {
+    let channels = hwmon_channels("pwm", "")?;
+    let channel = channels.get(index).ok_or_else(|| {
+        io::Error::new(io::ErrorKind::NotFound, format!("pwm channel {} not found", index))
+    })?;
+
+    let duty = u32::from(cmp::min(percent, 100)) * 255 / 100;
+    MANUAL_DUTY.store(true, Ordering::SeqCst);
+    let mut enable = channel.clone().into_os_string();
+    enable.push("_enable");
+    fs::write(enable, "1")?;
+    fs::write(channel, duty.to_string())
+}
+
+/// Returns every PWM channel to automatic control, if any were given a manual duty.
+pub fn restore_automatic() {
+    if !MANUAL_DUTY.swap(false, Ordering::SeqCst) {
+        return;
+    }
+
+    match hwmon_channels("pwm", "_enable") {
+        Ok(channels) => {
+            for channel in channels {
+                if let Err(why) = fs::write(&channel, "2") {
+                    log::warn!("failed to restore {}: {}", channel.display(), why);
+                }
+            }
+        }
+        Err(why) => log::warn!("failed to collect pwm channels: {}", why),
+    }
+}
+
 pub fn nvidia_temperatures<F: FnMut(u32)>(func: F) -> io::Result<()> {
     let output = Command::new("nvidia-smi")
         .arg("--query-gpu=temperature.gpu")
@@ -339,6 +470,18 @@ mod tests {
         assert_eq!(fan_point.get_duty_between_points(next_point, 3500), None);
     }
 
+    #[test]
+    fn parse_curve() {
+        assert_eq!(
+            "45:30, 65:40,88:100".parse::<FanCurve>(),
+            Ok(FanCurve::default().append(45_00, 30_00).append(65_00, 40_00).append(88_00, 100_00))
+        );
+        assert!("45:30,45:40".parse::<FanCurve>().is_err());
+        assert!("45:30,55:20".parse::<FanCurve>().is_err());
+        assert!("45:101".parse::<FanCurve>().is_err());
+        assert!("45".parse::<FanCurve>().is_err());
+    }
+
     #[test]
     fn standard_points() {
         let standard = FanCurve::standard();
@@ -1,15 +1,39 @@
-use crate::{hotplug, module::Module, pci::PciBus};
+use crate::{fan, model::Model, module::Module, pci::PciBus};
 use serde::{Deserialize, Serialize};
 use std::{
-    fs,
+    cell::Cell,
+    cmp, env, fmt, fs,
     io::{self, Write},
     iter::FromIterator,
+    path::Path,
     process::{self, ExitStatus},
+    str::FromStr,
+    sync::atomic::{AtomicBool, Ordering},
+    thread,
+    time::{Duration, Instant},
 };
-use sysfs_class::{PciDevice, SysClass};
+use sysfs_class::{PciDevice, RuntimePM, RuntimePowerManagement, SysClass};
 
 const MODPROBE_PATH: &str = "/etc/modprobe.d/system76-power.conf";
 
+const NVIDIA_FALLBACK_SERVICE: &str = "nvidia-fallback.service";
+
+const SYSTEMCTL_CMD: &str = "systemctl";
+
+// Files and services of other graphics switching tools, which conflict with system76-power.
+const BBSWITCH_CONF_PATH: &str = "/etc/modprobe.d/bbswitch.conf";
+const OPTIMUS_MANAGER_SERVICE: &str = "optimus-manager.service";
+const XORG_CONF_DIR: &str = "/etc/X11/xorg.conf.d";
+
+/// The environment variable which may redirect the modprobe file, such as for testing.
+const MODPROBE_PATH_VAR: &str = "SYSTEM76_POWER_MODPROBE_PATH";
+
+// Set while a graphics switch is rewriting the modprobe configuration and initramfs.
+static SWITCHING: AtomicBool = AtomicBool::new(false);
+
+// Whether switching regenerates the initramfs, which may be disabled by the daemon configuration.
+static REGENERATE_INITRAMFS: AtomicBool = AtomicBool::new(true);
+
 static MODPROBE_NVIDIA: &[u8] = br#"# Automatically generated by system76-power
 options nvidia-drm modeset=1
 "#;
@@ -29,6 +53,13 @@ alias i2c_nvidia_gpu off
 alias nvidia-drm off
 alias nvidia-modeset off
 options nvidia NVreg_DynamicPowerManagement=0x02
+softdep nvidia post: nvidia-uvm
+"#;
+
+static MODPROBE_INTEGRATED_PM: &[u8] = br#"# Automatically generated by system76-power
+blacklist i2c_nvidia_gpu
+alias i2c_nvidia_gpu off
+options nvidia NVreg_DynamicPowerManagement=0x02
 "#;
 
 static MODPROBE_INTEGRATED: &[u8] = br#"# Automatically generated by system76-power
@@ -44,26 +75,57 @@ alias nvidia-drm off
 alias nvidia-modeset off
 "#;
 
+// The lines delimiting the part of the modprobe file managed by system76-power. Lines outside
+// of the block belong to the user, and are preserved by a switch.
+const MODPROBE_BEGIN: &str = "# BEGIN system76-power";
+const MODPROBE_END: &str = "# END system76-power";
+
 const PRIME_DISCRETE_PATH: &str = "/etc/prime-discrete";
 
+const OSTREE_BOOTED_PATH: &str = "/run/ostree-booted";
+const NVIDIA_VERSION_PATH: &str = "/sys/module/nvidia/version";
+
 #[derive(Debug, err_derive::Error)]
 pub enum GraphicsDeviceError {
+    #[error(display = "a graphics switch or power profile change is already in progress")]
+    Busy,
     #[error(display = "failed to execute {} command: {}", cmd, why)]
     Command { cmd: &'static str, why: io::Error },
+    #[error(
+        display = "{} belongs to another graphics switching tool, remove it or switch with --force",
+        path
+    )]
+    ConflictingConfig { path: String },
+    #[error(display = "failed to set d3cold_allowed on {}: {}", device, why)]
+    D3Cold { device: String, why: io::Error },
+    #[error(display = "NVIDIA graphics in use by {}", processes)]
+    DeviceBusy { pids: Vec<u32>, processes: String },
     #[error(display = "{} in use by {}", func, driver)]
     DeviceInUse { func: String, driver: String },
+    #[error(display = "no NVIDIA graphics device at {}", _0)]
+    DeviceNotFound(String),
+    #[error(display = "{} failed with {} status", cmd, status)]
+    Initramfs { cmd: &'static str, status: ExitStatus },
     #[error(display = "failed to probe driver features: {}", _0)]
     Json(io::Error),
     #[error(display = "failed to open system76-power modprobe file: {}", _0)]
     ModprobeFileOpen(io::Error),
     #[error(display = "failed to write to system76-power modprobe file: {}", _0)]
     ModprobeFileWrite(io::Error),
+    #[error(display = "failed to replace system76-power modprobe file: {}", _0)]
+    ModprobeRename(io::Error),
+    #[error(display = "system76-power modprobe file does not have the intended content")]
+    ModprobeVerify,
     #[error(display = "failed to fetch list of active kernel modules: {}", _0)]
     ModulesFetch(io::Error),
+    #[error(display = "no supported initramfs generator was found")]
+    NoInitramfsTool,
     #[error(display = "does not have switchable graphics")]
     NotSwitchable,
     #[error(display = "PCI driver error on {}: {}", device, why)]
     PciDriver { device: String, why: io::Error },
+    #[error(display = "discrete graphics are powered off")]
+    PoweredOff,
     #[error(display = "failed to get PRIME value: {}", _0)]
     PrimeModeRead(io::Error),
     #[error(display = "failed to set PRIME value: {}", _0)]
@@ -72,14 +134,208 @@ pub enum GraphicsDeviceError {
     Remove { device: String, why: io::Error },
     #[error(display = "failed to rescan PCI bus: {}", _0)]
     Rescan(io::Error),
+    #[error(display = "NVIDIA graphics did not appear after rescanning PCI bus {} times", _0)]
+    RescanTimeout(u32),
+    #[error(display = "rpm-ostree initramfs-etc failed with {} status", _0)]
+    RpmOstree(ExitStatus),
+    #[error(display = "failed to set runtime power management on {}: {}", device, why)]
+    RuntimePm { device: String, why: io::Error },
+    #[error(display = "{} is masked, and must be unmasked with `systemctl unmask {0}`", _0)]
+    ServiceMasked(&'static str),
     #[error(display = "failed to read sysfs info: {}", _0)]
     SysFs(io::Error),
+    #[error(display = "NVIDIA graphics temperature is not available")]
+    TemperatureUnavailable,
     #[error(display = "failed to unbind {} on PCI driver {}: {}", func, driver, why)]
     Unbind { func: String, driver: String, why: io::Error },
-    #[error(display = "update-initramfs failed with {} status", _0)]
-    UpdateInitramfs(ExitStatus),
-    #[error(display = "update-initramfs didn't found tools and failed with {} status", _0)]
-    UpdateInitramfsNoTools(ExitStatus),
+    #[error(
+        display = "unknown graphics vendor '{}': expected integrated, integrated-pm, nvidia, \
+                   hybrid, or compute",
+        _0
+    )]
+    UnknownVendor(String),
+}
+
+impl GraphicsDeviceError {
+    /// The D-Bus error name which the daemon replies with for this error. The message of the
+    /// reply is the display of the error.
+    pub fn dbus_error_name(&self) -> &'static str {
+        use self::GraphicsDeviceError::*;
+        match self {
+            Busy => "com.system76.PowerDaemon.Error.Busy",
+            ConflictingConfig { .. } => "com.system76.PowerDaemon.Error.ConflictingConfig",
+            DeviceBusy { .. } => "com.system76.PowerDaemon.Error.DeviceBusy",
+            DeviceInUse { .. } => "com.system76.PowerDaemon.Error.DeviceInUse",
+            DeviceNotFound(_) => "com.system76.PowerDaemon.Error.DeviceNotFound",
+            Initramfs { .. } | RpmOstree(_) => "com.system76.PowerDaemon.Error.Initramfs",
+            NoInitramfsTool => "com.system76.PowerDaemon.Error.NoInitramfsTool",
+            NotSwitchable => "com.system76.PowerDaemon.Error.NotSwitchable",
+            PoweredOff => "com.system76.PowerDaemon.Error.PoweredOff",
+            RescanTimeout(_) => "com.system76.PowerDaemon.Error.RescanTimeout",
+            ServiceMasked(_) => "com.system76.PowerDaemon.Error.ServiceMasked",
+            TemperatureUnavailable => "com.system76.PowerDaemon.Error.TemperatureUnavailable",
+            UnknownVendor(_) => "com.system76.PowerDaemon.Error.UnknownVendor",
+            _ => "com.system76.PowerDaemon.Error.Failed",
+        }
+    }
+}
+
+/// The graphics modes which may be reported by, or requested from, the daemon.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GraphicsMode {
+    Integrated,
+    /// Like `Integrated`, but the NVIDIA drivers stay loaded, so that the dGPU can be used for
+    /// offloading, with runtime power management suspending it while it is idle.
+    IntegratedPowerManaged,
+    Nvidia,
+    Hybrid,
+    Compute,
+}
+
+impl fmt::Display for GraphicsMode {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            GraphicsMode::Integrated => "integrated",
+            GraphicsMode::IntegratedPowerManaged => "integrated-pm",
+            GraphicsMode::Nvidia => "nvidia",
+            GraphicsMode::Hybrid => "hybrid",
+            GraphicsMode::Compute => "compute",
+        })
+    }
+}
+
+impl FromStr for GraphicsMode {
+    type Err = String;
+
+    fn from_str(mode: &str) -> Result<Self, Self::Err> {
+        match mode {
+            "integrated" | "intel" => Ok(GraphicsMode::Integrated),
+            "integrated-pm" => Ok(GraphicsMode::IntegratedPowerManaged),
+            "nvidia" => Ok(GraphicsMode::Nvidia),
+            "hybrid" => Ok(GraphicsMode::Hybrid),
+            "compute" => Ok(GraphicsMode::Compute),
+            other => Err(format!("unknown graphics mode '{}'", other)),
+        }
+    }
+}
+
+/// A PCI function of a graphics device, as the device ID, function ID, class, vendor, and bound
+/// driver. The driver is empty for functions without one.
+pub type GraphicsFunction = (String, String, String, String, String);
+
+/// The power state of the discrete graphics.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GraphicsPowerState {
+    /// At least one function is present and active.
+    On,
+    /// Every function is present, but has been suspended by runtime power management.
+    Suspended,
+    /// Every function has been removed from the PCI bus.
+    Removed,
+}
+
+impl fmt::Display for GraphicsPowerState {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            GraphicsPowerState::On => "on",
+            GraphicsPowerState::Suspended => "suspended",
+            GraphicsPowerState::Removed => "removed",
+        })
+    }
+}
+
+impl FromStr for GraphicsPowerState {
+    type Err = String;
+
+    fn from_str(state: &str) -> Result<Self, Self::Err> {
+        match state {
+            "on" => Ok(GraphicsPowerState::On),
+            "suspended" => Ok(GraphicsPowerState::Suspended),
+            "removed" => Ok(GraphicsPowerState::Removed),
+            other => Err(format!("unknown graphics power state '{}'", other)),
+        }
+    }
+}
+
+/// Tools which may be used to regenerate the initramfs, in order of priority.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum InitramfsTool {
+    Dracut,
+    UpdateInitramfs,
+    Mkinitcpio,
+}
+
+impl InitramfsTool {
+    fn path(self) -> &'static str {
+        match self {
+            InitramfsTool::Dracut => "/usr/bin/dracut",
+            InitramfsTool::UpdateInitramfs => "/usr/sbin/update-initramfs",
+            InitramfsTool::Mkinitcpio => "/usr/bin/mkinitcpio",
+        }
+    }
+
+    /// The command and arguments that regenerate the initramfs for all kernels.
+    pub fn command(self) -> (&'static str, &'static [&'static str]) {
+        match self {
+            InitramfsTool::Dracut => ("dracut", &["--force"]),
+            InitramfsTool::UpdateInitramfs => ("update-initramfs", &["-u"]),
+            InitramfsTool::Mkinitcpio => ("mkinitcpio", &["-P"]),
+        }
+    }
+}
+
+/// Changes whether switching graphics modes regenerates the initramfs. Systems which do not
+/// load the NVIDIA blacklist from the initramfs, such as some systemd-boot setups, may skip it.
+pub fn set_regenerate_initramfs(enabled: bool) {
+    REGENERATE_INITRAMFS.store(enabled, Ordering::SeqCst);
+}
+
+/// Detect which initramfs generator is installed on the system.
+pub fn initramfs_tool() -> Option<InitramfsTool> {
+    [InitramfsTool::Dracut, InitramfsTool::UpdateInitramfs, InitramfsTool::Mkinitcpio]
+        .iter()
+        .cloned()
+        .find(|tool| Path::new(tool.path()).exists())
+}
+
+/// The version of the loaded NVIDIA driver, or an empty string if it is not loaded.
+pub fn nvidia_driver_version() -> String {
+    fs::read_to_string(NVIDIA_VERSION_PATH).map(|s| s.trim().to_owned()).unwrap_or_default()
+}
+
+/// Find the processes which hold open handles to the NVIDIA device nodes, along with their names.
+pub fn nvidia_processes() -> io::Result<Vec<(u32, String)>> {
+    processes_using(|target| target.starts_with("/dev/nvidia"))
+}
+
+/// Find the processes which hold open handles to a file whose path matches the predicate.
+fn processes_using<F: Fn(&str) -> bool>(uses: F) -> io::Result<Vec<(u32, String)>> {
+    let mut processes = Vec::new();
+    for entry in fs::read_dir("/proc")? {
+        let entry = entry?;
+        let pid = match entry.file_name().to_str().and_then(|name| name.parse::<u32>().ok()) {
+            Some(pid) => pid,
+            None => continue,
+        };
+
+        // Processes may exit while they are being inspected
+        let fds = match fs::read_dir(entry.path().join("fd")) {
+            Ok(fds) => fds,
+            Err(_) => continue,
+        };
+
+        let in_use = fds
+            .filter_map(Result::ok)
+            .filter_map(|fd| fs::read_link(fd.path()).ok())
+            .any(|target| uses(&target.to_string_lossy()));
+
+        if in_use {
+            let name = fs::read_to_string(entry.path().join("comm")).unwrap_or_default();
+            processes.push((pid, name.trim().to_owned()));
+        }
+    }
+
+    Ok(processes)
 }
 
 pub struct GraphicsDevice {
@@ -94,8 +350,118 @@ impl GraphicsDevice {
 
     pub fn exists(&self) -> bool { self.functions.iter().any(|func| func.path().exists()) }
 
-    pub unsafe fn unbind(&self) -> Result<(), GraphicsDeviceError> {
+    pub fn id(&self) -> &str { &self.id }
+
+    /// The functions of the device in descending order, so that the audio, USB, and serial
+    /// functions are released before the graphics function which they depend on.
+    fn functions_children_first(&self) -> Vec<&PciDevice> {
+        let mut functions: Vec<&PciDevice> = self.functions.iter().collect();
+        functions.sort_by(|a, b| b.id().cmp(a.id()));
+        functions
+    }
+
+    /// Whether the firmware booted with this device as the display controller.
+    pub fn is_boot_vga(&self) -> bool {
+        self.functions.iter().any(|func| {
+            fs::read_to_string(func.path().join("boot_vga")).map_or(false, |v| v.trim() == "1")
+        })
+    }
+
+    /// The functions which would keep the device from being powered off, as the function ID,
+    /// the bound driver, and the processes holding its device nodes open. The NVIDIA device
+    /// nodes are not mapped to PCI functions, so their users block every function bound to the
+    /// NVIDIA driver.
+    fn power_blockers(&self, nvidia_pids: &[u32]) -> io::Result<Vec<(String, String, Vec<u32>)>> {
+        let mut blockers = Vec::new();
+        for func in self.functions.iter().filter(|func| func.path().exists()) {
+            let driver = match func.driver() {
+                Ok(driver) => driver.id().to_owned(),
+                Err(_) => continue,
+            };
+
+            // The DRM card and render nodes of the function, such as `/dev/dri/renderD128`.
+            let nodes: Vec<String> = fs::read_dir(func.path().join("drm"))
+                .map(|entries| {
+                    entries
+                        .filter_map(Result::ok)
+                        .map(|entry| format!("/dev/dri/{}", entry.file_name().to_string_lossy()))
+                        .collect()
+                })
+                .unwrap_or_default();
+
+            let mut pids: Vec<u32> = if nodes.is_empty() {
+                Vec::new()
+            } else {
+                processes_using(|target| nodes.iter().any(|node| node == target))?
+                    .into_iter()
+                    .map(|(pid, _)| pid)
+                    .collect()
+            };
+
+            if driver == "nvidia" {
+                pids.extend_from_slice(nvidia_pids);
+            }
+
+            pids.sort();
+            pids.dedup();
+            if !pids.is_empty() {
+                blockers.push((func.id().to_owned(), driver, pids));
+            }
+        }
+
+        Ok(blockers)
+    }
+
+    pub fn power_state(&self) -> GraphicsPowerState {
+        if !self.exists() {
+            GraphicsPowerState::Removed
+        } else if self.is_suspended() {
+            GraphicsPowerState::Suspended
+        } else {
+            GraphicsPowerState::On
+        }
+    }
+
+    /// Whether every function that is present has been suspended by runtime power management.
+    pub fn is_suspended(&self) -> bool {
+        self.functions.iter().filter(|func| func.path().exists()).all(|func| {
+            fs::read_to_string(func.path().join("power/runtime_status"))
+                .map_or(false, |status| status.trim() == "suspended")
+        })
+    }
+
+    pub fn set_runtime_pm(&self, pm: RuntimePowerManagement) -> Result<(), GraphicsDeviceError> {
         for func in &self.functions {
+            if func.path().exists() {
+                func.set_runtime_pm(pm).map_err(|why| GraphicsDeviceError::RuntimePm {
+                    device: func.id().to_owned(),
+                    why,
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Permits or forbids each function of the device from entering D3cold while runtime
+    /// suspended. Functions without a `d3cold_allowed` file are skipped.
+    pub fn set_d3cold(&self, allowed: bool) -> Result<(), GraphicsDeviceError> {
+        for func in &self.functions {
+            let path = func.path().join("d3cold_allowed");
+            if path.exists() {
+                fs::write(&path, if allowed { "1" } else { "0" }).map_err(|why| {
+                    GraphicsDeviceError::D3Cold { device: func.id().to_owned(), why }
+                })?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Unbinds the driver of every function of the device, such as the HDMI audio, USB, and
+    /// serial functions, each of which may be bound to a different driver.
+    pub unsafe fn unbind(&self) -> Result<(), GraphicsDeviceError> {
+        for func in self.functions_children_first() {
             if func.path().exists() {
                 match func.driver() {
                     Ok(driver) => {
@@ -122,36 +488,44 @@ impl GraphicsDevice {
         Ok(())
     }
 
-    pub unsafe fn remove(&self) -> Result<(), GraphicsDeviceError> {
-        for func in &self.functions {
-            if func.path().exists() {
-                match func.driver() {
-                    Ok(driver) => {
-                        log::error!("{}: in use by {}", func.id(), driver.id());
-                        return Err(GraphicsDeviceError::DeviceInUse {
-                            func:   func.id().to_owned(),
-                            driver: driver.id().to_owned(),
-                        });
-                    }
-                    Err(why) => match why.kind() {
-                        io::ErrorKind::NotFound => {
-                            log::info!("{}: Removing", func.id());
-                            func.remove().map_err(|why| GraphicsDeviceError::Remove {
-                                device: self.id.clone(),
-                                why,
-                            })?;
-                        }
-                        _ => {
-                            return Err(GraphicsDeviceError::PciDriver {
-                                device: self.id.clone(),
-                                why,
-                            })
-                        }
-                    },
-                }
-            } else {
+    /// Removes every function of the device. A function which is still bound to a driver is
+    /// an error, unless `force` is set, in which case the driver is unbound first.
+    pub unsafe fn remove(&self, force: bool) -> Result<(), GraphicsDeviceError> {
+        for func in self.functions_children_first() {
+            if !func.path().exists() {
                 log::warn!("{}: Already removed", func.id());
+                continue;
             }
+
+            match func.driver() {
+                Ok(driver) if force => {
+                    log::warn!(
+                        "{}: FORCIBLY unbinding {}, which is still bound",
+                        func.id(),
+                        driver.id()
+                    );
+                    driver.unbind(func).map_err(|why| GraphicsDeviceError::Unbind {
+                        driver: driver.id().to_owned(),
+                        func: func.id().to_owned(),
+                        why,
+                    })?;
+                }
+                Ok(driver) => {
+                    log::error!("{}: in use by {}", func.id(), driver.id());
+                    return Err(GraphicsDeviceError::DeviceInUse {
+                        func:   func.id().to_owned(),
+                        driver: driver.id().to_owned(),
+                    });
+                }
+                Err(ref why) if why.kind() == io::ErrorKind::NotFound => (),
+                Err(why) => {
+                    return Err(GraphicsDeviceError::PciDriver { device: self.id.clone(), why })
+                }
+            }
+
+            log::info!("{}: Removing", func.id());
+            func.remove()
+                .map_err(|why| GraphicsDeviceError::Remove { device: self.id.clone(), why })?;
         }
 
         Ok(())
@@ -174,21 +548,158 @@ struct SupportedGpus {
     chips: Vec<NvidiaDevice>,
 }
 
+/// The modprobe file written by a graphics switch, which is `MODPROBE_PATH` unless the
+/// `SYSTEM76_POWER_MODPROBE_PATH` environment variable is set.
+fn modprobe_path() -> String {
+    env::var(MODPROBE_PATH_VAR).unwrap_or_else(|_| MODPROBE_PATH.to_owned())
+}
+
+/// Replaces the managed block of the modprobe file with `text`, and preserves every other
+/// line. The block is the last `BEGIN` line through the first `END` line after it, so that
+/// stray delimiters copied elsewhere in the file are left alone. The block is appended if the
+/// file does not have one yet. Files written before the block was introduced were generated in
+/// full, and are replaced entirely.
+fn merge_modprobe(existing: &str, text: &str) -> String {
+    let block = format!("{}\n{}{}\n", MODPROBE_BEGIN, text, MODPROBE_END);
+
+    let lines: Vec<&str> = existing.lines().collect();
+    let begin = lines.iter().rposition(|line| line.trim() == MODPROBE_BEGIN);
+    let end = begin.and_then(|begin| {
+        lines[begin..].iter().position(|line| line.trim() == MODPROBE_END).map(|end| begin + end)
+    });
+
+    match (begin, end) {
+        (Some(begin), Some(end)) => {
+            let mut merged = String::new();
+            for line in &lines[..begin] {
+                merged.push_str(line);
+                merged.push('\n');
+            }
+
+            merged.push_str(&block);
+            for line in &lines[end + 1..] {
+                merged.push_str(line);
+                merged.push('\n');
+            }
+
+            merged
+        }
+        _ if existing.trim().is_empty()
+            || existing.starts_with("# Automatically generated by system76-power") =>
+        {
+            block
+        }
+        _ => {
+            let separator = if existing.ends_with('\n') { "\n" } else { "\n\n" };
+            format!("{}{}{}", existing, separator, block)
+        }
+    }
+}
+
+/// The enablement state of a systemd unit, such as `enabled`, `disabled`, or `masked`, or
+/// `absent` if the unit is not installed.
+fn service_state(service: &str) -> String {
+    let state = process::Command::new(SYSTEMCTL_CMD)
+        .arg("is-enabled")
+        .arg(service)
+        .output()
+        .map(|output| String::from_utf8_lossy(&output.stdout).trim().to_owned())
+        .unwrap_or_default();
+
+    match state.as_str() {
+        "" | "not-found" => "absent".to_owned(),
+        _ => state,
+    }
+}
+
+/// Whether a systemd unit is masked, in which case it cannot be enabled.
+fn service_masked(service: &str) -> bool { service_state(service) == "masked" }
+
+/// The enablement state of `nvidia-fallback.service`, which is enabled by switching to the
+/// NVIDIA graphics: `enabled`, `disabled`, `masked`, or `absent`.
+pub fn nvidia_fallback_state() -> String { service_state(NVIDIA_FALLBACK_SERVICE) }
+
+/// The first file or active service of another graphics switching tool, such as bumblebee or
+/// optimus-manager, which would leave the system in a broken state after a switch.
+fn conflicting_config() -> Option<String> {
+    if Path::new(BBSWITCH_CONF_PATH).exists() {
+        return Some(BBSWITCH_CONF_PATH.to_owned());
+    }
+
+    let optimus_manager_active = process::Command::new(SYSTEMCTL_CMD)
+        .args(&["is-active", "--quiet", OPTIMUS_MANAGER_SERVICE])
+        .status()
+        .map_or(false, |status| status.success());
+    if optimus_manager_active {
+        return Some(OPTIMUS_MANAGER_SERVICE.to_owned());
+    }
+
+    fs::read_dir(XORG_CONF_DIR)
+        .ok()?
+        .filter_map(Result::ok)
+        .find(|entry| entry.file_name().to_string_lossy().to_lowercase().contains("nvidia"))
+        .map(|entry| entry.path().to_string_lossy().into_owned())
+}
+
+/// Held for the duration of a graphics vendor switch, or of a power profile change.
+///
+/// A switch and a power profile change both modify kernel module and sysfs state, so they are
+/// mutually exclusive: `Graphics::set_vendor` fails with `GraphicsDeviceError::Busy` while
+/// another switch or a profile change holds the guard, and the daemon holds it while applying
+/// a profile.
+pub struct SwitchGuard(());
+
+impl SwitchGuard {
+    /// Acquires the guard, or returns `None` if a switch or profile change is in progress.
+    pub fn try_acquire() -> Option<SwitchGuard> {
+        SWITCHING
+            .compare_exchange(false, true, Ordering::SeqCst, Ordering::SeqCst)
+            .ok()
+            .map(|_| SwitchGuard(()))
+    }
+}
+
+impl Drop for SwitchGuard {
+    fn drop(&mut self) { SWITCHING.store(false, Ordering::SeqCst); }
+}
+
+/// Whether a graphics vendor switch or power profile change is in progress.
+pub fn is_switching() -> bool { SWITCHING.load(Ordering::SeqCst) }
+
 pub struct Graphics {
     pub bus:    PciBus,
     pub amd:    Vec<GraphicsDevice>,
     pub intel:  Vec<GraphicsDevice>,
     pub nvidia: Vec<GraphicsDevice>,
     pub other:  Vec<GraphicsDevice>,
+    // When the NVIDIA graphics were first found idle by `auto_power_with_idle`.
+    idle_since: Cell<Option<Instant>>,
 }
 
 impl Graphics {
+    /// Rescans the PCI bus, so that devices removed by an earlier `set_power(false)` are found
+    /// again, and then enumerates the graphics devices.
     pub fn new() -> io::Result<Graphics> {
         let bus = PciBus::new()?;
 
         log::info!("Rescanning PCI bus");
         bus.rescan()?;
 
+        Self::enumerate(bus)
+    }
+
+    /// Enumerates the graphics devices which are currently on the bus, without rescanning it.
+    /// Devices removed by an earlier `set_power(false)` are not found until they are powered on
+    /// again, which rescans the bus.
+    pub fn new_without_rescan() -> io::Result<Graphics> { Self::enumerate(PciBus::new()?) }
+
+    /// Enumerates the graphics devices again, without rescanning the PCI bus.
+    pub fn refresh(&mut self) -> io::Result<()> {
+        *self = Self::new_without_rescan()?;
+        Ok(())
+    }
+
+    fn enumerate(bus: PciBus) -> io::Result<Graphics> {
         let devs = PciDevice::all()?;
 
         let functions = |parent: &PciDevice| -> Vec<PciDevice> {
@@ -197,7 +708,7 @@ impl Graphics {
                 for func in &devs {
                     if let Some(func_slot) = func.id().split('.').next() {
                         if func_slot == parent_slot {
-                            log::info!("{}: Function for {}", func.id(), parent.id());
+                            log::debug!("{}: Function for {}", func.id(), parent.id());
                             functions.push(func.clone());
                         }
                     }
@@ -215,47 +726,99 @@ impl Graphics {
             if let 0x03 = (c >> 16) & 0xFF {
                 match dev.vendor()? {
                     0x1002 => {
-                        log::info!("{}: AMD graphics", dev.id());
+                        log::debug!("{}: AMD graphics", dev.id());
                         amd.push(GraphicsDevice::new(dev.id().to_owned(), functions(&dev)));
                     }
                     0x10DE => {
-                        log::info!("{}: NVIDIA graphics", dev.id());
+                        log::debug!("{}: NVIDIA graphics", dev.id());
                         nvidia.push(GraphicsDevice::new(dev.id().to_owned(), functions(&dev)));
                     }
                     0x8086 => {
-                        log::info!("{}: Intel graphics", dev.id());
+                        log::debug!("{}: Intel graphics", dev.id());
                         intel.push(GraphicsDevice::new(dev.id().to_owned(), functions(&dev)));
                     }
                     vendor => {
-                        log::info!("{}: Other({:X}) graphics", dev.id(), vendor);
+                        log::debug!("{}: Other({:X}) graphics", dev.id(), vendor);
                         other.push(GraphicsDevice::new(dev.id().to_owned(), functions(&dev)));
                     }
                 }
             }
         }
 
-        Ok(Graphics { bus, amd, intel, nvidia, other })
+        Ok(Graphics { bus, amd, intel, nvidia, other, idle_since: Cell::new(None) })
+    }
+
+    /// The integrated graphics devices serving as the display controller. When both Intel and
+    /// AMD graphics are present, the vendor of the boot VGA device is chosen, preferring Intel
+    /// if neither is flagged.
+    pub fn integrated(&self) -> &[GraphicsDevice] {
+        match self.integrated_vendor() {
+            Some("amd") => &self.amd,
+            _ => &self.intel,
+        }
     }
 
-    pub fn can_switch(&self) -> bool {
-        !self.nvidia.is_empty() && (!self.intel.is_empty() || !self.amd.is_empty())
+    /// The vendor of the integrated graphics serving as the display controller, `intel` or
+    /// `amd`, or `None` if there are no integrated graphics.
+    pub fn integrated_vendor(&self) -> Option<&'static str> {
+        match (self.intel.is_empty(), self.amd.is_empty()) {
+            (true, true) => None,
+            (false, true) => Some("intel"),
+            (true, false) => Some("amd"),
+            (false, false) => {
+                if self.amd.iter().any(GraphicsDevice::is_boot_vga)
+                    && !self.intel.iter().any(GraphicsDevice::is_boot_vga)
+                {
+                    Some("amd")
+                } else {
+                    Some("intel")
+                }
+            }
+        }
     }
 
+    pub fn can_switch(&self) -> bool { !self.nvidia.is_empty() && !self.integrated().is_empty() }
+
     pub fn get_external_displays_require_dgpu(&self) -> Result<bool, GraphicsDeviceError> {
         self.switchable_or_fail()?;
 
-        let model = fs::read_to_string("/sys/class/dmi/id/product_version")
-            .map_err(GraphicsDeviceError::SysFs)?;
-
-        Ok(hotplug::REQUIRES_NVIDIA.contains(&model.trim()))
+        Ok(Model::detect().quirks().external_displays_require_dgpu)
     }
 
     fn nvidia_version(&self) -> Result<String, GraphicsDeviceError> {
-        fs::read_to_string("/sys/module/nvidia/version")
+        fs::read_to_string(NVIDIA_VERSION_PATH)
             .map_err(GraphicsDeviceError::SysFs)
             .map(|s| s.trim().to_string())
     }
 
+    /// The temperature of the NVIDIA graphics, in thousandths of a degree Celsius. This is read
+    /// from the hwmon of the PCI device if its driver provides one, and from `nvidia-smi`
+    /// otherwise. Fails with `PoweredOff` when the device has been removed.
+    pub fn nvidia_temperature(&self) -> Result<i32, GraphicsDeviceError> {
+        self.switchable_or_fail()?;
+
+        let device = &self.nvidia[0];
+        if !device.exists() {
+            return Err(GraphicsDeviceError::PoweredOff);
+        }
+
+        let hwmon = Path::new("/sys/bus/pci/devices").join(&device.id).join("hwmon");
+        if let Ok(entries) = fs::read_dir(hwmon) {
+            for entry in entries.filter_map(Result::ok) {
+                if let Ok(temp) = fs::read_to_string(entry.path().join("temp1_input")) {
+                    if let Ok(temp) = temp.trim().parse::<i32>() {
+                        return Ok(temp);
+                    }
+                }
+            }
+        }
+
+        let mut temp = None;
+        fan::nvidia_temperatures(|t| temp = cmp::max(temp, Some(t as i32)))
+            .map_err(GraphicsDeviceError::SysFs)?;
+        temp.map(|t| t * 1000).ok_or(GraphicsDeviceError::TemperatureUnavailable)
+    }
+
     fn get_nvidia_device_id(&self) -> Result<u32, GraphicsDeviceError> {
         let device = format!("/sys/bus/pci/devices/{}/device", self.nvidia[0].id);
         let id = fs::read_to_string(device).map_err(GraphicsDeviceError::SysFs)?;
@@ -299,26 +862,17 @@ impl Graphics {
     }
 
     pub fn get_default_graphics(&self) -> Result<String, GraphicsDeviceError> {
-        // Models that support runtimepm, but should not use hybrid graphics
-        const DEFAULT_INTEGRATED: &[&str] = &[];
-
         self.switchable_or_fail()?;
 
-        let product = fs::read_to_string("/sys/class/dmi/id/product_version")
-            .map_err(GraphicsDeviceError::SysFs)
-            .map(|s| s.trim().to_string())?;
-        let blacklisted = DEFAULT_INTEGRATED.contains(&product.as_str());
+        let model = Model::detect();
+        let blacklisted = model.quirks().default_integrated;
 
         // If the NVIDIA device is not on the bus or the drivers are not
         // loaded, then assume runtimepm is not supported.
         let runtimepm = self.gpu_supports_runtimepm().unwrap_or_default();
 
         // Only default to hybrid on System76 models
-        let vendor = fs::read_to_string("/sys/class/dmi/id/sys_vendor")
-            .map_err(GraphicsDeviceError::SysFs)
-            .map(|s| s.trim().to_string())?;
-
-        if vendor != "System76" {
+        if model.system76().is_none() {
             Ok("nvidia".to_string())
         } else if runtimepm && !blacklisted {
             Ok("hybrid".to_string())
@@ -337,7 +891,7 @@ impl Graphics {
         fs::write(PRIME_DISCRETE_PATH, mode).map_err(GraphicsDeviceError::PrimeModeWrite)
     }
 
-    pub fn get_vendor(&self) -> Result<String, GraphicsDeviceError> {
+    pub fn get_vendor(&self) -> Result<GraphicsMode, GraphicsDeviceError> {
         let modules = Module::all().map_err(GraphicsDeviceError::ModulesFetch)?;
         let vendor =
             if modules.iter().any(|module| module.name == "nouveau" || module.name == "nvidia") {
@@ -346,140 +900,338 @@ impl Graphics {
                     Err(_) => "nvidia".to_string(),
                 };
 
+                // Compute mode blacklists the DRM driver, which is used for offloading.
                 if mode == "on-demand" {
-                    "hybrid".to_string()
+                    GraphicsMode::Hybrid
+                } else if mode == "off" && modules.iter().any(|module| module.name == "nvidia_drm")
+                {
+                    GraphicsMode::IntegratedPowerManaged
                 } else if mode == "off" {
-                    "compute".to_string()
+                    GraphicsMode::Compute
                 } else {
-                    "nvidia".to_string()
+                    GraphicsMode::Nvidia
                 }
             } else {
-                "integrated".to_string()
+                GraphicsMode::Integrated
             };
 
         Ok(vendor)
     }
 
-    pub fn set_vendor(&self, vendor: &str) -> Result<(), GraphicsDeviceError> {
+    /// Switches to the given graphics mode, returning a description of each action taken. In a
+    /// dry run, the actions are only described, and nothing on the system is changed. The
+    /// switch fails if another graphics switching tool is configured, unless `force` is set.
+    pub fn set_vendor(
+        &self,
+        vendor: &str,
+        dry_run: bool,
+        force: bool,
+    ) -> Result<Vec<String>, GraphicsDeviceError> {
+        let vendor = vendor
+            .parse::<GraphicsMode>()
+            .map_err(|_| GraphicsDeviceError::UnknownVendor(vendor.to_owned()))?;
+
+        // Taken before anything else is checked, so that a concurrent switch or profile change
+        // is always reported as such.
+        let _guard = if dry_run {
+            None
+        } else {
+            Some(SwitchGuard::try_acquire().ok_or(GraphicsDeviceError::Busy)?)
+        };
+
         self.switchable_or_fail()?;
 
-        let mode = if vendor == "hybrid" {
-            "on-demand\n"
-        } else if vendor == "nvidia" {
-            "on\n"
+        let regenerate = REGENERATE_INITRAMFS.load(Ordering::SeqCst);
+        let ostree = Path::new(OSTREE_BOOTED_PATH).exists();
+        let tool = if ostree || !regenerate {
+            None
         } else {
-            // Integrated or Compute
-            "off\n"
+            Some(initramfs_tool().ok_or(GraphicsDeviceError::NoInitramfsTool)?)
         };
 
-        log::info!("Setting {} to {}", PRIME_DISCRETE_PATH, mode);
-        Self::set_prime_discrete(mode)?;
+        let mut actions = Vec::new();
+        let mut describe = |action: String| {
+            log::info!("{}{}", if dry_run { "Dry run: " } else { "" }, action);
+            actions.push(action);
+        };
 
-        {
-            log::info!("Creating {}", MODPROBE_PATH);
+        if let Some(path) = conflicting_config() {
+            if !force {
+                log::error!("{} belongs to another graphics switching tool", path);
+                return Err(GraphicsDeviceError::ConflictingConfig { path });
+            }
+
+            log::warn!("Switching despite {}, which belongs to another graphics tool", path);
+        }
+
+        // Enabling a masked unit silently does nothing, which would leave the NVIDIA mode
+        // without its fallback, so fail before anything is changed.
+        if vendor == GraphicsMode::Nvidia && service_masked(NVIDIA_FALLBACK_SERVICE) {
+            log::warn!("{} is masked", NVIDIA_FALLBACK_SERVICE);
+            return Err(GraphicsDeviceError::ServiceMasked(NVIDIA_FALLBACK_SERVICE));
+        }
+
+        let mode = match vendor {
+            GraphicsMode::Hybrid => "on-demand\n",
+            GraphicsMode::Nvidia => "on\n",
+            GraphicsMode::Integrated
+            | GraphicsMode::IntegratedPowerManaged
+            | GraphicsMode::Compute => "off\n",
+        };
+
+        describe(format!("Setting {} to {}", PRIME_DISCRETE_PATH, mode.trim()));
+        if !dry_run {
+            Self::set_prime_discrete(mode)?;
+        }
+
+        let text = match vendor {
+            GraphicsMode::Hybrid => MODPROBE_HYBRID,
+            GraphicsMode::Compute => MODPROBE_COMPUTE,
+            GraphicsMode::Nvidia => MODPROBE_NVIDIA,
+            GraphicsMode::Integrated => MODPROBE_INTEGRATED,
+            GraphicsMode::IntegratedPowerManaged => MODPROBE_INTEGRATED_PM,
+        };
+
+        let modprobe_path = modprobe_path();
+        describe(format!("Updating {}:\n{}", modprobe_path, String::from_utf8_lossy(text).trim()));
+        if !dry_run {
+            let existing = match fs::read_to_string(&modprobe_path) {
+                Ok(existing) => existing,
+                Err(ref why) if why.kind() == io::ErrorKind::NotFound => String::new(),
+                Err(why) => return Err(GraphicsDeviceError::ModprobeFileOpen(why)),
+            };
+
+            let merged = merge_modprobe(&existing, &String::from_utf8_lossy(text));
 
+            // Write to a temporary file in the same directory, and rename it over the modprobe
+            // file, so that the file always holds either the old or new content in full.
+            let temp_path = format!("{}.tmp", modprobe_path);
             let mut file = fs::OpenOptions::new()
                 .create(true)
                 .truncate(true)
                 .write(true)
-                .open(MODPROBE_PATH)
+                .open(&temp_path)
                 .map_err(GraphicsDeviceError::ModprobeFileOpen)?;
 
-            let text = if vendor == "hybrid" {
-                MODPROBE_HYBRID
-            } else if vendor == "compute" {
-                MODPROBE_COMPUTE
-            } else if vendor == "nvidia" {
-                MODPROBE_NVIDIA
-            } else {
-                MODPROBE_INTEGRATED
-            };
-
-            file.write_all(text)
+            file.write_all(merged.as_bytes())
                 .and_then(|_| file.sync_all())
                 .map_err(GraphicsDeviceError::ModprobeFileWrite)?;
+
+            fs::rename(&temp_path, &modprobe_path).map_err(GraphicsDeviceError::ModprobeRename)?;
+
+            // Confirm the content before the initramfs is regenerated around it.
+            let written =
+                fs::read(&modprobe_path).map_err(GraphicsDeviceError::ModprobeFileOpen)?;
+            if written != merged.as_bytes() {
+                return Err(GraphicsDeviceError::ModprobeVerify);
+            }
         }
 
-        const SYSTEMCTL_CMD: &str = "systemctl";
+        let action = if vendor == GraphicsMode::Nvidia { "enable" } else { "disable" };
 
-        let action = if vendor == "nvidia" {
-            log::info!("Enabling nvidia-fallback.service");
-            "enable"
-        } else {
-            log::info!("Disabling nvidia-fallback.service");
-            "disable"
-        };
+        describe(format!("Running {} {} {}", SYSTEMCTL_CMD, action, NVIDIA_FALLBACK_SERVICE));
+        if !dry_run {
+            let status = process::Command::new(SYSTEMCTL_CMD)
+                .arg(action)
+                .arg(NVIDIA_FALLBACK_SERVICE)
+                .status()
+                .map_err(|why| GraphicsDeviceError::Command { cmd: SYSTEMCTL_CMD, why })?;
+
+            if !status.success() {
+                // Error is ignored in case this service is removed
+                log::warn!(
+                    "systemctl: failed with {} (not an error if service does not exist!)",
+                    status
+                );
+            }
+        }
 
-        let status = process::Command::new(SYSTEMCTL_CMD)
-            .arg(action)
-            .arg("nvidia-fallback.service")
-            .status()
-            .map_err(|why| GraphicsDeviceError::Command { cmd: SYSTEMCTL_CMD, why })?;
-
-        if !status.success() {
-            // Error is ignored in case this service is removed
-            log::warn!(
-                "systemctl: failed with {} (not an error if service does not exist!)",
-                status
+        if !regenerate {
+            describe(
+                "Skipping initramfs regeneration, rebuild the initramfs manually if it loads the \
+                 NVIDIA modules, and reboot"
+                    .into(),
             );
+        } else if let Some(tool) = tool {
+            let (cmd, args) = tool.command();
+            describe(format!("Updating initramfs with {} {}", cmd, args.join(" ")));
+            if !dry_run {
+                let status = process::Command::new(cmd)
+                    .args(args)
+                    .status()
+                    .map_err(|why| GraphicsDeviceError::Command { cmd, why })?;
+
+                if !status.success() {
+                    return Err(GraphicsDeviceError::Initramfs { cmd, status });
+                }
+            }
+        } else {
+            // Image-based systems only pick up files in /etc which are tracked by rpm-ostree.
+            // The NVIDIA mode does not blacklist anything, so it does not need to be tracked.
+            const RPM_OSTREE_CMD: &str = "rpm-ostree";
+
+            let track = if vendor == GraphicsMode::Nvidia {
+                format!("--untrack={}", modprobe_path)
+            } else {
+                format!("--track={}", modprobe_path)
+            };
+
+            describe(format!("Running {} initramfs-etc --force-sync {}", RPM_OSTREE_CMD, track));
+            if !dry_run {
+                let status = process::Command::new(RPM_OSTREE_CMD)
+                    .arg("initramfs-etc")
+                    .arg("--force-sync")
+                    .arg(track)
+                    .status()
+                    .map_err(|why| GraphicsDeviceError::Command { cmd: RPM_OSTREE_CMD, why })?;
+
+                if !status.success() {
+                    return Err(GraphicsDeviceError::RpmOstree(status));
+                }
+            }
         }
 
-        log::info!("Updating initramfs");
-        
+        Ok(actions)
+    }
 
-        const COMMAND_CMD: &str = "command";
-        const UPDATE_DRACUT_CMD: &str = "dracut";
-        const UPDATE_INITRAMFS_CMD: &str = "update-initramfs";
+    pub fn get_power(&self) -> Result<bool, GraphicsDeviceError> {
+        self.switchable_or_fail()?;
+        Ok(self.nvidia.iter().any(GraphicsDevice::exists))
+    }
 
-        if process::Command::new(COMMAND_CMD).arg("-v").arg(UPDATE_DRACUT_CMD).stdout(process::Stdio::null()).status().map_err(|why| GraphicsDeviceError::Command { cmd: UPDATE_DRACUT_CMD, why })?.success() {
+    /// The vendor of the display controller which the firmware booted with, or `None` if no
+    /// device is flagged as the boot VGA device.
+    pub fn boot_vga(&self) -> Option<&'static str> {
+        self.devices().into_iter().find(|(dev, _)| dev.is_boot_vga()).map(|(_, vendor)| vendor)
+    }
 
-            let status = process::Command::new(UPDATE_DRACUT_CMD)
-                .arg("--force")
-                .status()
-                .map_err(|why| GraphicsDeviceError::Command { cmd: UPDATE_DRACUT_CMD, why })?;
-            if ! status.success() {
-                return Err(GraphicsDeviceError::UpdateInitramfs(status));
+    /// Each PCI function grouped under each graphics device.
+    pub fn list_functions(&self) -> Vec<GraphicsFunction> {
+        let mut functions = Vec::new();
+        for (dev, _) in self.devices() {
+            for func in dev.functions.iter().filter(|func| func.path().exists()) {
+                functions.push((
+                    dev.id.clone(),
+                    func.id().to_owned(),
+                    func.class().map_or_else(|_| "?".into(), |class| format!("{:06X}", class)),
+                    func.vendor().map_or_else(|_| "?".into(), |vendor| format!("{:04X}", vendor)),
+                    func.driver().map(|driver| driver.id().to_owned()).unwrap_or_default(),
+                ));
             }
+        }
 
-            
-        } else {
+        functions
+    }
 
-            let status = process::Command::new(UPDATE_INITRAMFS_CMD)
-                .arg("-u")
-                .status()
-                .map_err(|why| GraphicsDeviceError::Command { cmd: UPDATE_INITRAMFS_CMD, why })?;
-        
-            if ! status.success() {
-                return Err(GraphicsDeviceError::UpdateInitramfs(status));
-            }
+    /// Every graphics device, along with the name of its vendor.
+    pub fn devices(&self) -> Vec<(&GraphicsDevice, &'static str)> {
+        let vendors: [(&[GraphicsDevice], &'static str); 4] = [
+            (&self.intel, "intel"),
+            (&self.amd, "amd"),
+            (&self.nvidia, "nvidia"),
+            (&self.other, "other"),
+        ];
+
+        vendors
+            .iter()
+            .flat_map(|&(devices, vendor)| devices.iter().map(move |dev| (dev, vendor)))
+            .collect()
+    }
+
+    /// The NVIDIA functions which would keep the graphics from being powered off, without
+    /// changing any state.
+    pub fn power_blockers(&self) -> Result<Vec<(String, String, Vec<u32>)>, GraphicsDeviceError> {
+        self.switchable_or_fail()?;
+
+        let nvidia_pids: Vec<u32> = nvidia_processes()
+            .map_err(GraphicsDeviceError::SysFs)?
+            .into_iter()
+            .map(|(pid, _)| pid)
+            .collect();
 
+        let mut blockers = Vec::new();
+        for dev in self.nvidia.iter().filter(|dev| dev.exists()) {
+            blockers.extend(dev.power_blockers(&nvidia_pids).map_err(GraphicsDeviceError::SysFs)?);
         }
 
-        Ok(())
+        Ok(blockers)
     }
 
-    pub fn get_power(&self) -> Result<bool, GraphicsDeviceError> {
+    pub fn get_power_state(&self) -> Result<GraphicsPowerState, GraphicsDeviceError> {
         self.switchable_or_fail()?;
-        Ok(self.nvidia.iter().any(GraphicsDevice::exists))
+
+        let present = self.nvidia.iter().filter(|dev| dev.exists()).collect::<Vec<_>>();
+        Ok(if present.is_empty() {
+            GraphicsPowerState::Removed
+        } else if present.iter().all(|dev| dev.is_suspended()) {
+            GraphicsPowerState::Suspended
+        } else {
+            GraphicsPowerState::On
+        })
+    }
+
+    /// Powers the NVIDIA graphics on or off. Powering off fails if a function is still bound to
+    /// a driver, unless `force` is set.
+    pub fn set_power(&self, power: bool, force: bool) -> Result<(), GraphicsDeviceError> {
+        self.switchable_or_fail()?;
+        self.set_devices_power(&self.nvidia.iter().collect::<Vec<_>>(), power, force)
     }
 
-    pub fn set_power(&self, power: bool) -> Result<(), GraphicsDeviceError> {
+    /// Powers a single NVIDIA device on or off, identified by its PCI address.
+    pub fn set_power_for(
+        &self,
+        id: &str,
+        power: bool,
+        force: bool,
+    ) -> Result<(), GraphicsDeviceError> {
         self.switchable_or_fail()?;
 
+        let device = self
+            .nvidia
+            .iter()
+            .find(|dev| dev.id == id)
+            .ok_or_else(|| GraphicsDeviceError::DeviceNotFound(id.to_owned()))?;
+        self.set_devices_power(&[device], power, force)
+    }
+
+    fn set_devices_power(
+        &self,
+        devices: &[&GraphicsDevice],
+        power: bool,
+        force: bool,
+    ) -> Result<(), GraphicsDeviceError> {
         if power {
             log::info!("Enabling graphics power");
-            self.bus.rescan().map_err(GraphicsDeviceError::Rescan)?;
+            self.idle_since.set(None);
+            self.rescan_until_present(devices)?;
         } else {
             log::info!("Disabling graphics power");
 
             // TODO: Don't allow turning off power if nvidia_drm modeset is enabled
 
+            // Refuse to pull the device out from under running processes, before anything is
+            // unbound and the device is left in a half-removed state. The device nodes are not
+            // mapped to PCI devices, so any NVIDIA process blocks every device.
+            let processes = nvidia_processes().map_err(GraphicsDeviceError::SysFs)?;
+            if !processes.is_empty() {
+                let description = processes
+                    .iter()
+                    .map(|(pid, name)| format!("{} ({})", name, pid))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                log::error!("NVIDIA graphics in use by {}", description);
+                return Err(GraphicsDeviceError::DeviceBusy {
+                    pids:      processes.iter().map(|&(pid, _)| pid).collect(),
+                    processes: description,
+                });
+            }
+
             unsafe {
                 // Unbind NVIDIA graphics devices and their functions
-                let unbinds = self.nvidia.iter().map(|dev| dev.unbind());
+                let unbinds = devices.iter().map(|dev| dev.unbind());
 
                 // Remove NVIDIA graphics devices and their functions
-                let removes = self.nvidia.iter().map(|dev| dev.remove());
+                let removes = devices.iter().map(|dev| dev.remove(force));
 
                 Result::from_iter(unbinds.chain(removes))?;
             }
@@ -488,9 +1240,105 @@ impl Graphics {
         Ok(())
     }
 
+    /// Lets the kernel power down the NVIDIA graphics while they are idle, as an alternative
+    /// to removing the device with `set_power(false)`.
+    pub fn set_runtime_pm(&self, enabled: bool) -> Result<(), GraphicsDeviceError> {
+        self.switchable_or_fail()?;
+
+        if !self.nvidia.iter().any(GraphicsDevice::exists) {
+            return Err(GraphicsDeviceError::PoweredOff);
+        }
+
+        log::info!("{} runtime power management", if enabled { "Enabling" } else { "Disabling" });
+        let pm = if enabled { RuntimePowerManagement::On } else { RuntimePowerManagement::Off };
+        self.nvidia.iter().try_for_each(|dev| dev.set_runtime_pm(pm))
+    }
+
+    /// Permits or forbids the NVIDIA graphics from entering D3cold, the deepest idle state,
+    /// while runtime power management has suspended them.
+    pub fn set_d3cold(&self, allowed: bool) -> Result<(), GraphicsDeviceError> {
+        self.switchable_or_fail()?;
+
+        if !self.nvidia.iter().any(GraphicsDevice::exists) {
+            return Err(GraphicsDeviceError::PoweredOff);
+        }
+
+        log::info!("{} D3cold", if allowed { "Allowing" } else { "Forbidding" });
+        self.nvidia.iter().try_for_each(|dev| dev.set_d3cold(allowed))
+    }
+
+    /// The functions of the device may not have reappeared by the time that a rescan returns,
+    /// so the rescan is retried for up to 1.75 seconds.
+    fn rescan_until_present(&self, devices: &[&GraphicsDevice]) -> Result<(), GraphicsDeviceError> {
+        const ATTEMPTS: u32 = 8;
+        const INTERVAL: Duration = Duration::from_millis(250);
+
+        for attempt in 1..=ATTEMPTS {
+            self.bus.rescan().map_err(GraphicsDeviceError::Rescan)?;
+            if devices.iter().any(|dev| dev.exists()) {
+                return Ok(());
+            }
+
+            if attempt < ATTEMPTS {
+                log::warn!("NVIDIA graphics not found after rescan, retrying");
+                thread::sleep(INTERVAL);
+            }
+        }
+
+        Err(GraphicsDeviceError::RescanTimeout(ATTEMPTS))
+    }
+
+    /// Powers the NVIDIA graphics on or off to suit the current mode. In the power managed
+    /// integrated mode, they are left on the bus, and runtime power management is enabled so
+    /// that they are suspended while idle.
     pub fn auto_power(&self) -> Result<(), GraphicsDeviceError> {
         let vendor = self.get_vendor()?;
-        self.set_power(vendor != "integrated")
+        self.set_power(vendor != GraphicsMode::Integrated, false)?;
+        if vendor == GraphicsMode::IntegratedPowerManaged {
+            self.set_runtime_pm(true)?;
+        }
+
+        Ok(())
+    }
+
+    /// Like `auto_power`, but the NVIDIA graphics are only powered off after no process has
+    /// held them for `idle_secs`, even when a mode using them is chosen. This is meant to be
+    /// called periodically. A removed device cannot be opened, so it is powered on again on
+    /// demand, by `auto_power` or `set_power`, which also restart the idle window. The power
+    /// managed integrated mode relies on runtime power management instead, so the graphics
+    /// are never removed in it.
+    pub fn auto_power_with_idle(&self, idle_secs: u64) -> Result<(), GraphicsDeviceError> {
+        if !self.get_power()? {
+            return Ok(());
+        }
+
+        let vendor = self.get_vendor()?;
+        if vendor == GraphicsMode::IntegratedPowerManaged {
+            self.idle_since.set(None);
+            return Ok(());
+        }
+
+        if vendor == GraphicsMode::Integrated {
+            self.idle_since.set(None);
+            return self.set_power(false, false);
+        }
+
+        let busy = self.power_blockers()?.iter().any(|(_, _, pids)| !pids.is_empty());
+        if busy {
+            self.idle_since.set(None);
+            return Ok(());
+        }
+
+        let now = Instant::now();
+        let since = self.idle_since.get().unwrap_or(now);
+        self.idle_since.set(Some(since));
+        if now.duration_since(since) < Duration::from_secs(idle_secs) {
+            return Ok(());
+        }
+
+        log::info!("NVIDIA graphics idle for {} seconds", idle_secs);
+        self.idle_since.set(None);
+        self.set_power(false, false)
     }
 
     fn switchable_or_fail(&self) -> Result<(), GraphicsDeviceError> {
@@ -501,3 +1349,65 @@ impl Graphics {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_modprobe_preserves_user_lines() {
+        let block = "# BEGIN system76-power\nblacklist nouveau\n# END system76-power\n";
+        assert_eq!(merge_modprobe("", "blacklist nouveau\n"), block);
+        assert_eq!(
+            merge_modprobe(
+                "# Automatically generated by system76-power\nold\n",
+                "blacklist nouveau\n"
+            ),
+            block
+        );
+
+        let appended = merge_modprobe("options snd power_save=1", "blacklist nouveau\n");
+        assert_eq!(appended, format!("options snd power_save=1\n\n{}", block));
+
+        let existing =
+            "blacklist pcspkr\n# BEGIN system76-power\nold\n# END system76-power\nextra\n";
+        assert_eq!(
+            merge_modprobe(existing, "blacklist nouveau\n"),
+            format!("blacklist pcspkr\n{}extra\n", block)
+        );
+    }
+
+    #[test]
+    fn merge_modprobe_ignores_stray_delimiters() {
+        let block = "# BEGIN system76-power\nblacklist nouveau\n# END system76-power\n";
+
+        // An END above the block is not taken as its end, so the block is replaced in place.
+        let existing = "# END system76-power\nblacklist pcspkr\n# BEGIN system76-power\nold\n# \
+                        END system76-power\n";
+        let merged = merge_modprobe(existing, "blacklist nouveau\n");
+        assert_eq!(merged, format!("# END system76-power\nblacklist pcspkr\n{}", block));
+        assert_eq!(merge_modprobe(&merged, "blacklist nouveau\n"), merged);
+
+        // A BEGIN without an END keeps the lines after it, and a block is appended once.
+        let existing = "# BEGIN system76-power\noptions snd power_save=1\n";
+        let merged = merge_modprobe(existing, "blacklist nouveau\n");
+        assert_eq!(merged, format!("{}\n{}", existing, block));
+        assert_eq!(merge_modprobe(&merged, "blacklist nouveau\n"), merged);
+    }
+
+    #[test]
+    fn graphics_mode_names_round_trip() {
+        let modes = [
+            GraphicsMode::Integrated,
+            GraphicsMode::IntegratedPowerManaged,
+            GraphicsMode::Nvidia,
+            GraphicsMode::Hybrid,
+            GraphicsMode::Compute,
+        ];
+        for &mode in &modes {
+            assert_eq!(mode.to_string().parse::<GraphicsMode>(), Ok(mode));
+        }
+
+        assert_eq!("intel".parse::<GraphicsMode>(), Ok(GraphicsMode::Integrated));
+    }
+}
@@ -1,9 +1,14 @@
 #![deny(clippy::all)]
 #![allow(clippy::missing_safety_doc)]
 
+pub mod backlight;
 pub mod charge_thresholds;
 pub mod client;
+pub mod config;
+pub mod cpu;
+pub mod cpufreq;
 pub mod daemon;
+pub mod ddc;
 pub mod disks;
 pub mod errors;
 pub mod fan;
@@ -11,19 +16,26 @@ pub mod graphics;
 pub mod hid_backlight;
 pub mod hotplug;
 pub mod kernel_parameters;
+pub mod lid;
 pub mod logging;
+pub mod model;
 pub mod modprobe;
 pub mod module;
 pub mod mux;
 pub mod pci;
+pub mod platform_profile;
 pub mod polkit;
+pub mod power_supply;
 pub mod radeon;
 pub mod sideband;
 pub mod snd;
+pub mod thermal;
 pub mod util;
 pub mod wifi;
 
 use charge_thresholds::ChargeProfile;
+use graphics::{GraphicsFunction, GraphicsMode, GraphicsPowerState};
+use std::collections::HashMap;
 
 pub static DBUS_NAME: &str = "com.system76.PowerDaemon";
 pub static DBUS_PATH: &str = "/com/system76/PowerDaemon";
@@ -33,18 +45,80 @@ pub trait Power {
     fn performance(&mut self) -> Result<(), String>;
     fn balanced(&mut self) -> Result<(), String>;
     fn battery(&mut self) -> Result<(), String>;
+    fn set_custom_profile(
+        &mut self,
+        min_perf_pct: u8,
+        max_perf_pct: u8,
+        no_turbo: bool,
+        governor: &str,
+    ) -> Result<(), String>;
     fn get_external_displays_require_dgpu(&mut self) -> Result<bool, String>;
     fn get_default_graphics(&mut self) -> Result<String, String>;
-    fn get_graphics(&mut self) -> Result<String, String>;
+    fn get_graphics(&mut self) -> Result<GraphicsMode, String>;
     fn get_profile(&mut self) -> Result<String, String>;
     fn get_switchable(&mut self) -> Result<bool, String>;
     fn set_graphics(&mut self, vendor: &str) -> Result<(), String>;
+    fn set_graphics_force(&mut self, vendor: &str) -> Result<(), String>;
+    fn set_graphics_dry_run(&mut self, vendor: &str) -> Result<Vec<String>, String>;
+    fn get_nvidia_version(&mut self) -> Result<String, String>;
+    fn get_nvidia_fallback_enabled(&mut self) -> Result<String, String>;
+    fn get_integrated_vendor(&mut self) -> Result<String, String>;
+    fn get_nvidia_temperature(&mut self) -> Result<i32, String>;
     fn get_graphics_power(&mut self) -> Result<bool, String>;
+    fn get_graphics_power_state(&mut self) -> Result<GraphicsPowerState, String>;
     fn set_graphics_power(&mut self, power: bool) -> Result<(), String>;
+    fn set_graphics_power_force(&mut self, power: bool) -> Result<(), String>;
+    fn set_graphics_power_for(&mut self, id: &str, power: bool) -> Result<(), String>;
+    fn get_boot_vga(&mut self) -> Result<String, String>;
+    fn get_pstate(&mut self) -> Result<(u8, u8, bool), String>;
+    fn list_graphics_functions(&mut self) -> Result<Vec<GraphicsFunction>, String>;
+    fn get_graphics_power_blockers(&mut self) -> Result<Vec<(String, String, Vec<u32>)>, String>;
+    fn list_graphics_devices(&mut self) -> Result<Vec<(String, String, String)>, String>;
     fn auto_graphics_power(&mut self) -> Result<(), String>;
+    fn set_graphics_runtime_pm(&mut self, enabled: bool) -> Result<(), String>;
+    fn set_graphics_d3cold(&mut self, allowed: bool) -> Result<(), String>;
     fn get_charge_thresholds(&mut self) -> Result<(u8, u8), String>;
     fn set_charge_thresholds(&mut self, thresholds: (u8, u8)) -> Result<(), String>;
     fn get_charge_profiles(&mut self) -> Result<Vec<ChargeProfile>, String>;
+    fn get_charge_profile(&mut self) -> Result<String, String>;
+    fn set_charge_profile(&mut self, profile: &str) -> Result<(), String>;
+    fn get_cpu_frequencies(&mut self) -> Result<Vec<u32>, String>;
+    fn get_thermal_state(&mut self) -> Result<(i32, bool), String>;
+    fn get_temperatures(&mut self) -> Result<HashMap<String, i32>, String>;
+    fn get_cpu_temperature(&mut self) -> Result<i32, String>;
+    fn get_model(&mut self) -> Result<String, String>;
+    fn get_firmware_versions(&mut self) -> Result<(String, String), String>;
+    fn set_display_brightness(&mut self, percent: u8, fade_ms: u32) -> Result<(), String>;
+    fn increase_display_brightness(&mut self, step: u8) -> Result<u8, String>;
+    fn decrease_display_brightness(&mut self, step: u8) -> Result<u8, String>;
+    fn increase_keyboard_brightness(&mut self, device: i32) -> Result<u8, String>;
+    fn decrease_keyboard_brightness(&mut self, device: i32) -> Result<u8, String>;
+    fn set_external_brightness(&mut self, display: u32, percent: u8) -> Result<(), String>;
+    fn set_keyboard_color(&mut self, zone: &str, color: &str) -> Result<(), String>;
+    fn get_fan_speeds(&mut self) -> Result<Vec<u32>, String>;
+    fn set_fan_duty(&mut self, index: u32, percent: u8) -> Result<(), String>;
+    fn set_fan_curve(&mut self, curve: &str) -> Result<(), String>;
+    fn set_auto_profile_switch(&mut self, enabled: bool) -> Result<(), String>;
+    fn reload_config(&mut self) -> Result<(), String>;
+    fn get_turbo(&mut self) -> Result<bool, String>;
+    fn set_turbo(&mut self, enabled: bool) -> Result<(), String>;
+    fn get_smt(&mut self) -> Result<bool, String>;
+    fn set_smt(&mut self, enabled: bool) -> Result<(), String>;
+    fn set_auto_brightness(&mut self, enabled: bool) -> Result<(), String>;
+    fn set_keyboard_timeout(&mut self, seconds: u32) -> Result<(), String>;
+    fn set_online_cores(&mut self, count: u32) -> Result<(), String>;
+    fn get_platform_profile(&mut self) -> Result<String, String>;
+    fn get_battery_health(&mut self) -> Result<(u8, u32), String>;
+    fn get_power_draw(&mut self) -> Result<f64, String>;
+    fn get_battery_status(&mut self) -> Result<(String, u64), String>;
+    fn get_available_governors(&mut self) -> Result<Vec<String>, String>;
+    fn get_governors(&mut self) -> Result<Vec<String>, String>;
+    fn set_governor(&mut self, governor: &str) -> Result<(), String>;
+    fn set_core_governor(&mut self, core: u32, governor: &str) -> Result<(), String>;
+    fn set_cpu_frequency(&mut self, core: u32, frequency: u32) -> Result<(), String>;
+    fn get_perf_percent(&mut self) -> Result<(u8, u8), String>;
+    fn set_min_perf_percent(&mut self, percent: u8) -> Result<(), String>;
+    fn set_max_perf_percent(&mut self, percent: u8) -> Result<(), String>;
 }
 
 // Helper function for errors
@@ -0,0 +1,27 @@
+use std::{fs, path::Path};
+
+const LID_PATH: &str = "/proc/acpi/button/lid";
+
+/// Whether the laptop lid is closed. Returns `None` on systems without a lid switch, such as
+/// desktops.
+pub fn closed() -> Option<bool> {
+    let mut found = false;
+    for entry in Path::new(LID_PATH).read_dir().ok()?.filter_map(Result::ok) {
+        // Each lid has a state file such as `state:      closed`
+        let state = match fs::read_to_string(entry.path().join("state")) {
+            Ok(state) => state,
+            Err(_) => continue,
+        };
+
+        found = true;
+        if state.split_whitespace().last() == Some("closed") {
+            return Some(true);
+        }
+    }
+
+    if found {
+        Some(false)
+    } else {
+        None
+    }
+}
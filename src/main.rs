@@ -6,6 +6,10 @@ use std::process;
 use system76_power::{charge_thresholds::get_charge_profiles, client, daemon, logging};
 
 fn main() {
+    let force = Arg::with_name("force")
+        .long("force")
+        .help("Switch even if another graphics switching tool is configured");
+
     let matches = App::new("system76-power")
         .about("Utility for managing graphics and power profiles")
         .version(env!("CARGO_PKG_VERSION"))
@@ -35,7 +39,19 @@ fn main() {
                         .help("Set the verbosity of daemon logs to 'debug' [default is 'info']")
                         .global(true)
                         .group("verbosity"),
-                ),
+                )
+                .arg(
+                    Arg::with_name("log-level")
+                        .long("log-level")
+                        .takes_value(true)
+                        .possible_values(&["off", "error", "warn", "info", "debug", "trace"])
+                        .help("Set the verbosity of daemon logs [default is 'info']")
+                        .global(true)
+                        .group("verbosity"),
+                )
+                .arg(Arg::with_name("watch").long("watch").help(
+                    "Print power profile changes from a running daemon, instead of starting one",
+                )),
         )
         .subcommand(
             SubCommand::with_name("profile")
@@ -50,6 +66,12 @@ fn main() {
                         .help("set the power profile")
                         .possible_values(&["battery", "balanced", "performance"])
                         .required(false),
+                )
+                .arg(
+                    Arg::with_name("json")
+                        .long("json")
+                        .help("Print the queried profile as JSON")
+                        .conflicts_with("profile"),
                 ),
         )
         .subcommand(
@@ -60,20 +82,51 @@ fn main() {
                      graphics profile will be queried\n - Otherwise, that profile will be set, if \
                      it is a valid profile\n\nA reboot is required after switching modes.",
                 )
+                .arg(
+                    Arg::with_name("dry-run")
+                        .long("dry-run")
+                        .help("Print the actions needed to switch modes, without performing them")
+                        .global(true),
+                )
                 .subcommand(
                     SubCommand::with_name("compute")
-                        .about("Like integrated, but the dGPU is available for compute"),
+                        .about("Like integrated, but the dGPU is available for compute")
+                        .arg(force.clone()),
                 )
                 .subcommand(
                     SubCommand::with_name("hybrid")
-                        .about("Set the graphics mode to Hybrid (PRIME)"),
+                        .about("Set the graphics mode to Hybrid (PRIME)")
+                        .arg(force.clone()),
                 )
                 .subcommand(
                     SubCommand::with_name("integrated")
-                        .about("Set the graphics mode to integrated"),
+                        .about("Set the graphics mode to integrated")
+                        .arg(force.clone()),
+                )
+                .subcommand(
+                    SubCommand::with_name("integrated-pm")
+                        .about(
+                            "Like integrated, but the dGPU is kept available for offloading, and \
+                             suspended while idle",
+                        )
+                        .arg(force.clone()),
+                )
+                .subcommand(
+                    SubCommand::with_name("status")
+                        .about("Print a report of the graphics state, for bug reports"),
+                )
+                .subcommand(
+                    SubCommand::with_name("functions")
+                        .about("List the PCI functions of each graphics device, with their driver"),
+                )
+                .subcommand(
+                    SubCommand::with_name("list")
+                        .about("List the graphics devices, with their vendor and power state"),
                 )
                 .subcommand(
-                    SubCommand::with_name("nvidia").about("Set the graphics mode to NVIDIA"),
+                    SubCommand::with_name("nvidia")
+                        .about("Set the graphics mode to NVIDIA")
+                        .arg(force),
                 )
                 .subcommand(
                     SubCommand::with_name("switchable")
@@ -86,6 +139,18 @@ fn main() {
                             Arg::with_name("state")
                                 .help("Set whether discrete graphics should be on or off")
                                 .possible_values(&["auto", "off", "on"]),
+                        )
+                        .arg(
+                            Arg::with_name("device")
+                                .long("device")
+                                .takes_value(true)
+                                .value_name("pci-address")
+                                .help("Only power the NVIDIA device at this PCI address on or off"),
+                        )
+                        .arg(
+                            Arg::with_name("force")
+                                .long("force")
+                                .help("Power off even if a driver is still bound to the device"),
                         ),
                 ),
         )
@@ -138,17 +203,150 @@ fn main() {
                         .required(false),
                 ),
         )
+        .subcommand(
+            SubCommand::with_name("backlight")
+                .about("Step the display backlight brightness up or down")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("up")
+                        .about("Increase the display brightness")
+                        .arg(Arg::with_name("step").help("percentage to step by [default: 5]")),
+                )
+                .subcommand(
+                    SubCommand::with_name("down")
+                        .about("Decrease the display brightness")
+                        .arg(Arg::with_name("step").help("percentage to step by [default: 5]")),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("keyboard-backlight")
+                .about("Step the keyboard backlight brightness up or down")
+                .setting(AppSettings::SubcommandRequiredElseHelp)
+                .subcommand(
+                    SubCommand::with_name("up").about("Increase the keyboard brightness").arg(
+                        Arg::with_name("device")
+                            .long("device")
+                            .takes_value(true)
+                            .help("index of the keyboard backlight to step [default: all of them]"),
+                    ),
+                )
+                .subcommand(
+                    SubCommand::with_name("down").about("Decrease the keyboard brightness").arg(
+                        Arg::with_name("device")
+                            .long("device")
+                            .takes_value(true)
+                            .help("index of the keyboard backlight to step [default: all of them]"),
+                    ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("cpu")
+                .about("Query or set CPU frequency scaling")
+                .subcommand(
+                    SubCommand::with_name("governor")
+                        .about("Set the governor of every core, or of a single core")
+                        .arg(
+                            Arg::with_name("name")
+                                .help("the governor to use, from `cpu governors`")
+                                .required(true),
+                        )
+                        .arg(
+                            Arg::with_name("core")
+                                .long("core")
+                                .takes_value(true)
+                                .help("index of the core to set [default: all of them]"),
+                        ),
+                )
+                .subcommand(
+                    SubCommand::with_name("governors")
+                        .about("List the governors supported by the cpufreq driver"),
+                )
+                .subcommand(
+                    SubCommand::with_name("min")
+                        .about("Set the minimum frequency, as a percentage of the maximum")
+                        .arg(Arg::with_name("percent").required(true)),
+                )
+                .subcommand(
+                    SubCommand::with_name("max")
+                        .about("Set the maximum frequency, as a percentage of the maximum")
+                        .arg(Arg::with_name("percent").required(true)),
+                )
+                .subcommand(
+                    SubCommand::with_name("turbo").about("Query or set frequency boosting").arg(
+                        Arg::with_name("state")
+                            .help("Set whether turbo should be on or off")
+                            .possible_values(&["off", "on"]),
+                    ),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("info")
+                .about("Print the model and firmware versions, for support requests"),
+        )
+        .subcommand(
+            SubCommand::with_name("monitor")
+                .about("Print daemon events and CPU state as they change, until interrupted")
+                .arg(
+                    Arg::with_name("interval")
+                        .long("interval")
+                        .takes_value(true)
+                        .value_name("seconds")
+                        .help("Seconds between prints of the CPU state [default: 5]"),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("reload")
+                .about("Reload the daemon configuration from /etc/system76-power/config.toml"),
+        )
+        .subcommand(
+            SubCommand::with_name("temps").about("Print the temperature of every thermal sensor"),
+        )
+        .subcommand(
+            SubCommand::with_name("smt")
+                .about("Query or set simultaneous multithreading (hyperthreading)")
+                .arg(
+                    Arg::with_name("state")
+                        .help("Set whether SMT should be on or off")
+                        .possible_values(&["off", "on"]),
+                ),
+        )
+        .subcommand(
+            SubCommand::with_name("charge-profile")
+                .about("Query or set the battery charge profile")
+                .long_about(
+                    "Queries or sets the battery charge profile.\n\n - If an argument is not \
+                     provided, the profile matching the current thresholds will be queried, or \
+                     'custom' if none match\n - Otherwise, the thresholds of that profile will be \
+                     set",
+                )
+                .arg(
+                    Arg::with_name("profile")
+                        .help("set the charge profile")
+                        .possible_values(
+                            &get_charge_profiles()
+                                .iter()
+                                .map(|p| p.id.as_str())
+                                .collect::<Vec<_>>(),
+                        )
+                        .required(false),
+                ),
+        )
         .get_matches();
 
     let res = match matches.subcommand() {
+        ("daemon", Some(matches)) if matches.is_present("watch") => client::watch(),
         ("daemon", Some(matches)) => {
-            if let Err(why) = logging::setup(if matches.is_present("verbose") {
+            let filter = if let Some(level) = matches.value_of("log-level") {
+                level.parse::<LevelFilter>().unwrap_or(LevelFilter::Info)
+            } else if matches.is_present("verbose") {
                 LevelFilter::Debug
             } else if matches.is_present("quiet") {
                 LevelFilter::Off
             } else {
                 LevelFilter::Info
-            }) {
+            };
+
+            if let Err(why) = logging::setup(filter) {
                 eprintln!("failed to set up logging: {}", why);
                 process::exit(1);
             }
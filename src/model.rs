@@ -0,0 +1,82 @@
+use crate::hotplug;
+use std::{fmt, fs};
+
+const PRODUCT_NAME_PATH: &str = "/sys/class/dmi/id/product_name";
+const PRODUCT_VERSION_PATH: &str = "/sys/class/dmi/id/product_version";
+const SYS_VENDOR_PATH: &str = "/sys/class/dmi/id/sys_vendor";
+const BIOS_VERSION_PATH: &str = "/sys/class/dmi/id/bios_version";
+const EC_FIRMWARE_RELEASE_PATH: &str = "/sys/class/dmi/id/ec_firmware_release";
+
+// Models that support runtimepm, but should not use hybrid graphics
+const DEFAULT_INTEGRATED: &[&str] = &[];
+
+/// The machine that the daemon is running on, read from the DMI tables.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Model {
+    /// A System76 machine, by the model in its product version, such as `galp5`.
+    System76(String),
+    /// A machine from any other vendor, by its vendor and product name.
+    Other { vendor: String, product: String },
+}
+
+impl Model {
+    /// Reads the model from the DMI tables. Values which cannot be read are left empty.
+    pub fn detect() -> Self {
+        let vendor = read_dmi(SYS_VENDOR_PATH);
+        if vendor == "System76" {
+            Model::System76(read_dmi(PRODUCT_VERSION_PATH))
+        } else {
+            Model::Other { vendor, product: read_dmi(PRODUCT_NAME_PATH) }
+        }
+    }
+
+    /// The System76 model, such as `galp5`, or `None` on machines from other vendors.
+    pub fn system76(&self) -> Option<&str> {
+        match self {
+            Model::System76(model) => Some(model),
+            Model::Other { .. } => None,
+        }
+    }
+
+    /// The behavior which differs between models. Unknown models use the defaults.
+    pub fn quirks(&self) -> Quirks {
+        let model = match self.system76() {
+            Some(model) => model,
+            None => return Quirks::default(),
+        };
+
+        Quirks {
+            external_displays_require_dgpu: hotplug::REQUIRES_NVIDIA.contains(&model),
+            default_integrated:             DEFAULT_INTEGRATED.contains(&model),
+        }
+    }
+}
+
+impl fmt::Display for Model {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Model::System76(model) => write!(f, "System76 {}", model),
+            Model::Other { vendor, product } => write!(f, "{} {}", vendor, product),
+        }
+    }
+}
+
+/// Behavior which differs between models.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Quirks {
+    /// Whether the external display ports are wired to the NVIDIA graphics.
+    pub external_displays_require_dgpu: bool,
+    /// Whether the integrated mode is the default, even if the NVIDIA graphics support runtime
+    /// power management.
+    pub default_integrated:             bool,
+}
+
+/// The BIOS version, and the version of the embedded controller firmware. The EC version is
+/// empty on firmware which does not report it.
+pub fn firmware_versions() -> (String, String) {
+    (read_dmi(BIOS_VERSION_PATH), read_dmi(EC_FIRMWARE_RELEASE_PATH))
+}
+
+fn read_dmi(path: &str) -> String {
+    fs::read_to_string(path).map(|value| value.trim().to_owned()).unwrap_or_default()
+}
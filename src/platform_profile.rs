@@ -0,0 +1,35 @@
+use std::{fs, io, path::Path};
+
+const PLATFORM_PROFILE: &str = "/sys/firmware/acpi/platform_profile";
+const PLATFORM_PROFILE_CHOICES: &str = "/sys/firmware/acpi/platform_profile_choices";
+
+/// Whether the firmware exposes the ACPI platform profile interface.
+pub fn is_supported() -> bool { Path::new(PLATFORM_PROFILE).exists() }
+
+/// The platform profiles which the firmware supports, such as `low-power` and `performance`.
+pub fn choices() -> io::Result<Vec<String>> {
+    fs::read_to_string(PLATFORM_PROFILE_CHOICES)
+        .map(|choices| choices.split_whitespace().map(String::from).collect())
+}
+
+/// The active platform profile.
+pub fn get() -> io::Result<String> {
+    fs::read_to_string(PLATFORM_PROFILE).map(|profile| profile.trim().to_owned())
+}
+
+/// Sets the platform profile, after validating it against the supported choices. Returns
+/// `false` if the firmware does not expose the interface.
+pub fn set(profile: &str) -> io::Result<bool> {
+    if !is_supported() {
+        return Ok(false);
+    }
+
+    if !choices()?.iter().any(|choice| choice == profile) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("platform profile {} is not available", profile),
+        ));
+    }
+
+    fs::write(PLATFORM_PROFILE, profile).map(|_| true)
+}
@@ -0,0 +1,106 @@
+use std::{cmp, fs, io, path::Path};
+
+const POWER_SUPPLY_PATH: &str = "/sys/class/power_supply";
+const BATTERY_PATH: &str = "/sys/class/power_supply/BAT0";
+
+fn read_value(path: &Path) -> io::Result<u64> {
+    fs::read_to_string(path)?
+        .trim()
+        .parse::<u64>()
+        .map_err(|why| io::Error::new(io::ErrorKind::InvalidData, why))
+}
+
+/// Whether any AC adapter is supplying power. Returns `None` on systems without an AC adapter,
+/// such as desktops.
+pub fn ac_online() -> Option<bool> {
+    let mut found = false;
+    for entry in Path::new(POWER_SUPPLY_PATH).read_dir().ok()?.filter_map(Result::ok) {
+        let path = entry.path();
+        let kind = fs::read_to_string(path.join("type")).unwrap_or_default();
+        if kind.trim() != "Mains" {
+            continue;
+        }
+
+        found = true;
+        if fs::read_to_string(path.join("online")).map_or(false, |online| online.trim() == "1") {
+            return Some(true);
+        }
+    }
+
+    if found {
+        Some(false)
+    } else {
+        None
+    }
+}
+
+/// The charge of the battery, as a percentage of its full capacity.
+pub fn battery_percent() -> io::Result<u8> {
+    read_value(&Path::new(BATTERY_PATH).join("capacity"))
+        .map(|percent| cmp::min(percent, 100) as u8)
+}
+
+/// The capacity of the battery as a percentage of its design capacity, along with its charge
+/// cycle count. Batteries which report `energy_*` values instead of `charge_*` are supported,
+/// and the cycle count is zero if the battery does not report one.
+pub fn battery_health() -> io::Result<(u8, u32)> {
+    let battery = Path::new(BATTERY_PATH);
+    let prefix = if battery.join("charge_full").exists() { "charge" } else { "energy" };
+
+    let full = read_value(&battery.join(format!("{}_full", prefix)))?;
+    let design = read_value(&battery.join(format!("{}_full_design", prefix)))?;
+    if design == 0 {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "battery design capacity is zero"));
+    }
+
+    let health = cmp::min(full * 100 / design, 100) as u8;
+    let cycles = read_value(&battery.join("cycle_count")).unwrap_or(0) as u32;
+    Ok((health, cycles))
+}
+
+/// The status of the battery, such as `Charging`, `Discharging`, or `Full`, along with the
+/// estimated number of seconds until it is full while charging, or empty while discharging.
+/// The estimate is zero when it is unknown, such as when no current is flowing.
+pub fn battery_status() -> io::Result<(String, u64)> {
+    let battery = Path::new(BATTERY_PATH);
+    let status = fs::read_to_string(battery.join("status"))?.trim().to_owned();
+
+    // The charge values are paired with the current, and the energy values with the power.
+    let (prefix, rate) = if battery.join("charge_now").exists() {
+        ("charge", "current")
+    } else {
+        ("energy", "power")
+    };
+
+    let rate = read_value(&battery.join(format!("{}_now", rate))).unwrap_or(0);
+    if rate == 0 {
+        return Ok((status, 0));
+    }
+
+    let now = read_value(&battery.join(format!("{}_now", prefix)))?;
+    let remaining = match status.as_str() {
+        "Charging" => read_value(&battery.join(format!("{}_full", prefix)))?.saturating_sub(now),
+        "Discharging" => now,
+        _ => return Ok((status, 0)),
+    };
+
+    // Microamp hours divided by microamps, or microwatt hours divided by microwatts, give hours.
+    Ok((status, remaining * 3600 / rate))
+}
+
+/// The power currently drawn from the battery, in watts. Some firmware reports `power_now`
+/// directly, while others only report `current_now` and `voltage_now`.
+pub fn power_draw_watts() -> io::Result<f64> {
+    let battery = Path::new(BATTERY_PATH);
+    let microwatts = match read_value(&battery.join("power_now")) {
+        Ok(power) => power as f64,
+        Err(_) => {
+            let current = read_value(&battery.join("current_now"))? as f64;
+            let voltage = read_value(&battery.join("voltage_now"))? as f64;
+            // Microamps multiplied by microvolts gives picowatts.
+            current * voltage / 1_000_000.0
+        }
+    };
+
+    Ok(microwatts / 1_000_000.0)
+}
@@ -0,0 +1,153 @@
+use crate::{cpufreq, fan};
+use std::{collections::HashMap, fs, path::Path};
+
+const THERMAL_PATH: &str = "/sys/class/thermal";
+
+/// The hwmon drivers which report CPU package temperatures, and the labels of their package
+/// sensors. Intel coretemp labels it `Package id 0`, and AMD k10temp labels it `Tctl`.
+const CPU_HWMONS: [(&str, &[&str]); 2] =
+    [("coretemp", &["Package id 0"]), ("k10temp", &["Tctl", "Tdie"])];
+
+/// The total number of times that any core has been thermally throttled since boot.
+fn throttle_count() -> u64 {
+    (0..cpufreq::num_cpus().unwrap_or(0))
+        .filter_map(|core| {
+            fs::read_to_string(format!(
+                "/sys/devices/system/cpu/cpu{}/thermal_throttle/core_throttle_count",
+                core
+            ))
+            .ok()
+        })
+        .filter_map(|count| count.trim().parse::<u64>().ok())
+        .sum()
+}
+
+/// Detects thermal throttling by watching for changes in the per-core throttle counters.
+pub struct ThrottleMonitor {
+    last_count: u64,
+}
+
+impl Default for ThrottleMonitor {
+    fn default() -> Self { ThrottleMonitor { last_count: throttle_count() } }
+}
+
+impl ThrottleMonitor {
+    /// Returns true if any core was throttled since the monitor was created, or since the
+    /// previous call.
+    pub fn is_throttling(&mut self) -> bool {
+        let count = throttle_count();
+        let throttling = count > self.last_count;
+        self.last_count = count;
+        throttling
+    }
+}
+
+/// The temperature of the hottest thermal zone, in thousandths of a degree Celsius.
+pub fn max_temperature() -> Option<i32> {
+    let zones = Path::new(THERMAL_PATH).read_dir().ok()?;
+    zones
+        .filter_map(Result::ok)
+        .filter(|zone| zone.file_name().to_string_lossy().starts_with("thermal_zone"))
+        .filter_map(|zone| fs::read_to_string(zone.path().join("temp")).ok())
+        .filter_map(|temp| temp.trim().parse::<i32>().ok())
+        .max()
+}
+
+fn read_temperature(path: &Path) -> Option<i32> {
+    fs::read_to_string(path).ok()?.trim().parse::<i32>().ok()
+}
+
+/// The hwmon name and sensor label of a `temp{n}_input` file. Sensors without a label are named
+/// by their channel, such as `temp1`.
+fn sensor_names(input: &Path) -> (String, String) {
+    let hwmon = input.parent().unwrap_or_else(|| Path::new(""));
+    let channel = input.file_name().unwrap_or_default().to_string_lossy().replace("_input", "");
+    let hwmon_name = fs::read_to_string(hwmon.join("name"))
+        .map_or_else(|_| "hwmon".into(), |name| name.trim().to_owned());
+    let label = fs::read_to_string(hwmon.join(format!("{}_label", channel)))
+        .map_or_else(|_| channel.clone(), |label| label.trim().to_owned());
+    (hwmon_name, label)
+}
+
+/// The temperature of the CPU package, in thousandths of a degree Celsius. This is read from
+/// the `x86_pkg_temp` thermal zone, or else from the package sensor of the coretemp or k10temp
+/// hwmon, falling back to the first channel of the hwmon.
+pub fn cpu_temperature() -> Option<i32> {
+    if let Ok(zones) = Path::new(THERMAL_PATH).read_dir() {
+        for zone in zones.filter_map(Result::ok) {
+            let kind = fs::read_to_string(zone.path().join("type")).unwrap_or_default();
+            if kind.trim() == "x86_pkg_temp" {
+                if let Some(temp) = read_temperature(&zone.path().join("temp")) {
+                    return Some(temp);
+                }
+            }
+        }
+    }
+
+    let inputs = fan::hwmon_channels("temp", "_input").unwrap_or_default();
+    for &(driver, labels) in &CPU_HWMONS {
+        let mut first = None;
+        for input in &inputs {
+            let (hwmon_name, label) = sensor_names(input);
+            if hwmon_name != driver {
+                continue;
+            }
+
+            if labels.contains(&label.as_str()) {
+                if let Some(temp) = read_temperature(input) {
+                    return Some(temp);
+                }
+            }
+
+            first = first.or_else(|| read_temperature(input));
+        }
+
+        if first.is_some() {
+            return first;
+        }
+    }
+
+    None
+}
+
+/// Inserts a temperature, disambiguating sensors which share a name with a suffix.
+fn insert_unique(temps: &mut HashMap<String, i32>, name: String, source: &str, temp: i32) {
+    let name = if temps.contains_key(&name) { format!("{} ({})", name, source) } else { name };
+    temps.insert(name, temp);
+}
+
+/// The temperature of every thermal zone, keyed by the zone type, and of every hwmon sensor,
+/// keyed by the hwmon name and the sensor label, in thousandths of a degree Celsius. Sensors
+/// which disappear or fail to read are skipped.
+pub fn temperatures() -> HashMap<String, i32> {
+    let mut temps = HashMap::new();
+
+    if let Ok(zones) = Path::new(THERMAL_PATH).read_dir() {
+        for zone in zones.filter_map(Result::ok) {
+            let zone_name = zone.file_name().to_string_lossy().into_owned();
+            if !zone_name.starts_with("thermal_zone") {
+                continue;
+            }
+
+            let path = zone.path();
+            let kind = fs::read_to_string(path.join("type")).ok();
+            if let (Some(kind), Some(temp)) = (kind, read_temperature(&path.join("temp"))) {
+                insert_unique(&mut temps, kind.trim().to_owned(), &zone_name, temp);
+            }
+        }
+    }
+
+    for input in fan::hwmon_channels("temp", "_input").unwrap_or_default() {
+        let temp = match read_temperature(&input) {
+            Some(temp) => temp,
+            None => continue,
+        };
+
+        let (hwmon_name, label) = sensor_names(&input);
+        let hwmon = input.parent().unwrap_or_else(|| Path::new(""));
+        let source = hwmon.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        insert_unique(&mut temps, format!("{} {}", hwmon_name, label), &source, temp);
+    }
+
+    temps
+}